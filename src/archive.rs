@@ -0,0 +1,297 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+
+use tempfile::TempDir;
+
+use crate::error::SQLError;
+
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+/// Archives inside of archives are expanded up to this depth
+const MAX_DEPTH: usize = 4;
+
+/// Bigger members are skipped, so a small archive can't fill the disk
+const MAX_MEMBER_SIZE: u64 = 4 << 30;
+/// Extraction fails when extracted files take more
+const MAX_TOTAL_SIZE: u64 = 16 << 30;
+
+#[derive(Clone, Copy, Debug)]
+enum Format {
+    Gzip,
+    Zstd,
+    Zip,
+    Tar,
+}
+
+/// SQLite databases extracted from a compressed file or an archive
+pub(crate) struct Extracted {
+    /// Directory with extracted files, removed when dropped
+    pub dir: TempDir,
+    /// Archive path with member name and path to extracted database
+    pub databases: Vec<(String, PathBuf)>,
+}
+
+/// Extract databases if file is compressed or archived.
+///
+/// `None` is returned for any other file, so SQLite deals with it as usual.
+pub(crate) fn extract_databases(path: &Path) -> Result<Option<Extracted>, SQLError> {
+    let label = path.display().to_string();
+    let Some(format) = detect_format(path).map_err(|error| io_error(&label, error))? else {
+        return Ok(None);
+    };
+
+    log::debug!("Extract {format:?} file {label}");
+
+    let dir = tempfile::Builder::new()
+        .prefix("sqlgrep-")
+        .tempdir()
+        .map_err(|error| io_error(&label, error))?;
+
+    let mut extractor = Extractor {
+        dir: dir.path().to_owned(),
+        next: 0,
+        size: 0,
+        databases: vec![],
+    };
+    extractor.expand(path, format, &label, 0)?;
+
+    if extractor.databases.is_empty() {
+        return Err(SQLError::Configuration(format!(
+            "no SQLite databases found in {label}"
+        )));
+    }
+
+    Ok(Some(Extracted {
+        dir,
+        databases: extractor.databases,
+    }))
+}
+
+struct Extractor {
+    dir: PathBuf,
+    next: usize,
+    /// Size of all extracted files
+    size: u64,
+    databases: Vec<(String, PathBuf)>,
+}
+
+impl Extractor {
+    /// New directory to extract a file to
+    fn target(&mut self) -> Result<PathBuf, std::io::Error> {
+        self.next += 1;
+        let target = self.dir.join(self.next.to_string());
+        std::fs::create_dir(&target)?;
+        Ok(target)
+    }
+
+    /// Write a member unless it's neither a database, nor its journal, nor an archive.
+    ///
+    /// `false` is returned when the member is skipped.
+    fn write_member(
+        &mut self,
+        mut member: impl Read,
+        file: &Path,
+        label: &str,
+    ) -> Result<bool, std::io::Error> {
+        let header = read_header(&mut member)?;
+
+        let name = file.to_string_lossy();
+        let is_journal = name.ends_with("-wal") || name.ends_with("-journal");
+        if !header.starts_with(SQLITE_HEADER) && format_of(&header).is_none() && !is_journal {
+            log::debug!("Skip {label}: not a SQLite database");
+            return Ok(false);
+        }
+
+        if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut output = File::create(file)?;
+        // One more byte than allowed tells that the limit is exceeded
+        let allowed = (MAX_TOTAL_SIZE - self.size).min(MAX_MEMBER_SIZE);
+        let written = std::io::copy(
+            &mut header.as_slice().chain(member).take(allowed + 1),
+            &mut output,
+        )?;
+
+        if written > MAX_MEMBER_SIZE {
+            drop(output);
+            std::fs::remove_file(file)?;
+            log::warn!("Skip {label}: bigger than {MAX_MEMBER_SIZE} bytes");
+            return Ok(false);
+        }
+        if written > allowed {
+            return Err(std::io::Error::other(format!(
+                "extracted files take more than {MAX_TOTAL_SIZE} bytes"
+            )));
+        }
+
+        self.size += written;
+        Ok(true)
+    }
+
+    fn expand(
+        &mut self,
+        path: &Path,
+        format: Format,
+        label: &str,
+        depth: usize,
+    ) -> Result<(), SQLError> {
+        let error = |error| io_error(label, error);
+
+        let files = match format {
+            Format::Gzip | Format::Zstd => {
+                let file = BufReader::new(File::open(path).map_err(error)?);
+                let reader: Box<dyn Read> = match format {
+                    Format::Gzip => Box::new(flate2::bufread::MultiGzDecoder::new(file)),
+                    _ => Box::new(zstd::Decoder::with_buffer(file).map_err(error)?),
+                };
+
+                // Compressed file has the same label as the file itself
+                let name = path.file_stem().unwrap_or("database".as_ref());
+                let target = self.target().map_err(error)?.join(name);
+                match self.write_member(reader, &target, label).map_err(error)? {
+                    true => vec![(label.to_owned(), target)],
+                    false => vec![],
+                }
+            }
+            Format::Zip => self.extract_zip(path, label).map_err(error)?,
+            Format::Tar => self.extract_tar(path, label).map_err(error)?,
+        };
+
+        for (label, file) in files {
+            if is_sqlite(&file).map_err(|error| io_error(&label, error))? {
+                self.databases.push((label, file));
+                continue;
+            }
+
+            match detect_format(&file).map_err(|error| io_error(&label, error))? {
+                Some(format) if depth < MAX_DEPTH => {
+                    self.expand(&file, format, &label, depth + 1)?
+                }
+                Some(_) => log::warn!("Skip {label}: archive is nested too deep"),
+                None => log::debug!("Skip {label}: not a SQLite database"),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Members are extracted together, so write-ahead logs stay next to databases
+    fn extract_zip(
+        &mut self,
+        path: &Path,
+        label: &str,
+    ) -> Result<Vec<(String, PathBuf)>, std::io::Error> {
+        let target = self.target()?;
+        let mut archive = zip::ZipArchive::new(BufReader::new(File::open(path)?))
+            .map_err(std::io::Error::other)?;
+
+        let mut files = vec![];
+        for index in 0..archive.len() {
+            let mut member = archive.by_index(index).map_err(std::io::Error::other)?;
+            if !member.is_file() {
+                continue;
+            }
+
+            let Some(name) = member.enclosed_name() else {
+                log::warn!("Skip {label}:{}: unsafe path", member.name());
+                continue;
+            };
+
+            let (file, label) = (target.join(&name), format!("{label}:{}", name.display()));
+            if self.write_member(&mut member, &file, &label)? {
+                files.push((label, file));
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn extract_tar(
+        &mut self,
+        path: &Path,
+        label: &str,
+    ) -> Result<Vec<(String, PathBuf)>, std::io::Error> {
+        let target = self.target()?;
+        let mut archive = tar::Archive::new(BufReader::new(File::open(path)?));
+
+        let mut files = vec![];
+        for member in archive.entries()? {
+            let mut member = member?;
+            if !member.header().entry_type().is_file() {
+                continue;
+            }
+
+            let name = member.path()?.into_owned();
+            let Some(file) = enclosed_path(&target, &name) else {
+                log::warn!("Skip {label}:{}: unsafe path", name.display());
+                continue;
+            };
+
+            let label = format!("{label}:{}", name.display());
+            if self.write_member(&mut member, &file, &label)? {
+                files.push((label, file));
+            }
+        }
+
+        Ok(files)
+    }
+}
+
+/// Path of a member inside the target directory, `None` if it points outside
+fn enclosed_path(target: &Path, name: &Path) -> Option<PathBuf> {
+    let mut path = target.to_owned();
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    (path != target).then_some(path)
+}
+
+/// The first bytes of a file, enough to detect its format
+fn read_header(reader: &mut impl Read) -> Result<Vec<u8>, std::io::Error> {
+    let mut header = vec![];
+    reader.take(512).read_to_end(&mut header)?;
+    Ok(header)
+}
+
+/// Detect format by magic bytes, file extension doesn't matter
+fn detect_format(path: &Path) -> Result<Option<Format>, std::io::Error> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    Ok(format_of(&read_header(&mut File::open(path)?)?))
+}
+
+fn format_of(header: &[u8]) -> Option<Format> {
+    if header.starts_with(&[0x1f, 0x8b]) {
+        Some(Format::Gzip)
+    } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Some(Format::Zstd)
+    } else if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+        Some(Format::Zip)
+    } else if header.get(257..262) == Some(b"ustar") {
+        Some(Format::Tar)
+    } else {
+        None
+    }
+}
+
+fn is_sqlite(path: &Path) -> Result<bool, std::io::Error> {
+    let mut header = [0; SQLITE_HEADER.len()];
+    match File::open(path)?.read_exact(&mut header) {
+        Ok(()) => Ok(header == SQLITE_HEADER),
+        Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+#[inline]
+fn io_error(label: &str, error: std::io::Error) -> SQLError {
+    SQLError::Io((format!("extract {label}"), error))
+}
@@ -1,8 +1,15 @@
-use clap::{ArgAction, Parser};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{ArgAction, ArgMatches, CommandFactory as _, FromArgMatches as _, Parser, ValueEnum};
 use indoc::indoc;
 
-pub(crate) fn parse_args() -> Args {
-    Args::parse()
+/// Parse command line. Matches are kept to tell which options are set explicitly
+pub(crate) fn parse_args() -> (Args, ArgMatches) {
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+
+    (args, matches)
 }
 
 #[derive(Parser, Debug)]
@@ -17,6 +24,18 @@ pub(crate) struct Args {
     #[command(flatten)]
     pub(crate) query: QueryArgs,
 
+    #[command(flatten)]
+    pub(crate) scan: ScanArgs,
+
+    #[command(flatten)]
+    pub(crate) connection: ConnectionArgs,
+
+    #[command(flatten)]
+    pub(crate) output: OutputArgs,
+
+    #[command(flatten)]
+    pub(crate) config: ConfigArgs,
+
     #[arg(help = indoc!("
     Database URI to connect to.
 
@@ -26,9 +45,15 @@ pub(crate) struct Args {
     If none of options provided, uri provided support direct filename.
 
     Currently supported databases: SQLite 3.x with prefix sqlite
+
+    Compressed (gzip, zstd) and archived (zip, tar) SQLite files
+    are extracted to a temporary directory and every database in them is scanned.
+
+    Files with .sql extension are SQL dumps loaded into an in-memory database.
     "
     ))]
-    pub(crate) database_uri: String,
+    #[arg(required_unless_present = "from_dump", conflicts_with = "from_dump")]
+    pub(crate) database_uri: Option<String>,
 }
 
 #[derive(Parser, Debug)]
@@ -51,6 +76,54 @@ pub struct PatternArgs {
     #[arg(action=ArgAction::SetTrue)]
     pub(crate) fixed: bool,
 
+    #[arg(short = 'X', long = "hex-pattern")]
+    #[arg(help = "Pattern is hex bytes to find in BLOB cells, e.g. DEADBEEF")]
+    #[arg(action=ArgAction::SetTrue, conflicts_with_all = ["fixed", "byte_regex"])]
+    pub(crate) hex: bool,
+
+    #[arg(long = "byte-regex")]
+    #[arg(help = "Pattern is a regular expression over BLOB bytes, \\xNN matches a byte")]
+    #[arg(action=ArgAction::SetTrue, conflicts_with = "fixed")]
+    pub(crate) byte_regex: bool,
+
+    #[arg(long = "encodings")]
+    #[arg(help = indoc!("
+    Also search fixed string pattern in these encodings, plain value is always searched.
+    BLOB cells are matched too, every match tells the encoding"
+    ))]
+    #[arg(value_enum, value_delimiter = ',', value_name = "ENCODINGS")]
+    #[arg(conflicts_with_all = ["hex", "byte_regex"])]
+    pub(crate) encodings: Vec<PatternEncoding>,
+
+    #[arg(long = "decode-depth")]
+    #[arg(help = indoc!("
+    Decode base64, hex, URL encoding, HTML entities, JSON strings and compressed data
+    in cell values up to N nested layers and match every layer"
+    ))]
+    #[arg(default_value_t = 0, value_name = "N")]
+    pub(crate) decode_depth: usize,
+
+    #[arg(long = "json-cells")]
+    #[arg(
+        help = "Match keys and values of JSON documents in TEXT cells separately, output includes JSON path"
+    )]
+    #[arg(action=ArgAction::SetTrue)]
+    pub(crate) json_cells: bool,
+
+    #[arg(long = "json-path")]
+    #[arg(
+        help = "Match only under this path of JSON documents, e.g. $.user.emails[*]. Can be used multiple times, implies --json-cells"
+    )]
+    #[arg(action=ArgAction::Append, value_name = "PATH")]
+    pub(crate) json_path: Vec<String>,
+
+    #[arg(long = "protobuf")]
+    #[arg(
+        help = "Parse BLOB cells as protobuf messages without schema and match their fields, output includes field numbers path"
+    )]
+    #[arg(action=ArgAction::SetTrue)]
+    pub(crate) protobuf: bool,
+
     #[arg(short = 'W', long = "pattern-whole")]
     #[arg(help = "Pattern matches whole string")]
     #[arg(action=ArgAction::SetTrue)]
@@ -61,6 +134,11 @@ pub struct PatternArgs {
     #[arg(action=ArgAction::SetTrue)]
     pub(crate) case_insensitive: bool,
 
+    #[arg(short = 'b', long = "pattern-blobs")]
+    #[arg(help = "Pattern matches BLOB cells as bytes")]
+    #[arg(action=ArgAction::SetTrue)]
+    pub(crate) blobs: bool,
+
     #[arg(help = "Pattern to match every cell with")]
     pub(crate) pattern: String,
 }
@@ -81,6 +159,262 @@ pub struct QueryArgs {
     #[arg(help = "Ignore non-readonly queries")]
     #[arg(action=ArgAction::SetTrue)]
     pub(crate) ignore_non_readonly: bool,
+
+    #[arg(short = 'P', long = "prefilter")]
+    #[arg(help = "Filter table rows in SQL before matching. SQL queries are not filtered")]
+    #[arg(action=ArgAction::SetTrue)]
+    pub(crate) prefilter: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct ScanArgs {
+    #[arg(short = 'j', long = "jobs")]
+    #[arg(help = "Number of tables or queries to scan at the same time")]
+    #[arg(default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub(crate) jobs: u32,
+
+    #[arg(short = 'm', long = "max-count")]
+    #[arg(help = "Stop scanning a table or query after N matches")]
+    pub(crate) max_count: Option<u64>,
+
+    #[arg(long = "max-total-count")]
+    #[arg(help = "Stop scanning after N matches in total")]
+    pub(crate) max_total_count: Option<u64>,
+
+    #[arg(long = "max-rows")]
+    #[arg(help = "Fetch at most N rows from every table or query")]
+    pub(crate) max_rows: Option<u64>,
+
+    #[arg(long = "sample")]
+    #[arg(
+        help = "Scan roughly PCT percent of table rows selected by rowid. SQL queries are not sampled"
+    )]
+    #[arg(value_name = "PCT", value_parser = parse_percent)]
+    pub(crate) sample: Option<f64>,
+
+    #[arg(long = "timeout")]
+    #[arg(help = "Stop a table or query which runs longer than DURATION, e.g. 30s, 5m, 1h")]
+    #[arg(value_name = "DURATION", value_parser = parse_duration)]
+    pub(crate) timeout: Option<Duration>,
+
+    #[arg(long = "total-timeout")]
+    #[arg(help = "Stop scanning when it runs longer than DURATION, e.g. 30s, 5m, 1h")]
+    #[arg(value_name = "DURATION", value_parser = parse_duration)]
+    pub(crate) total_timeout: Option<Duration>,
+
+    #[arg(long = "max-errors")]
+    #[arg(
+        help = "Stop scanning a table or query after more than N cell errors. Error reading rows always stops it"
+    )]
+    #[arg(default_value_t = 100)]
+    pub(crate) max_errors: u64,
+
+    #[arg(long = "sort")]
+    #[arg(help = "Output matches grouped by table or query in order they are passed")]
+    #[arg(action=ArgAction::SetTrue)]
+    pub(crate) sort: bool,
+
+    #[arg(long = "no-progress")]
+    #[arg(help = "Don't report progress. Progress is reported only to a terminal")]
+    #[arg(action=ArgAction::SetTrue)]
+    pub(crate) no_progress: bool,
+
+    #[arg(long = "stats")]
+    #[arg(help = "Print summary statistics to stderr after all queries finish")]
+    #[arg(action=ArgAction::SetTrue)]
+    pub(crate) stats: bool,
+
+    #[arg(long = "carve")]
+    #[arg(
+        help = "Also search deleted records left in free space of the database file, output includes page and offset"
+    )]
+    #[arg(action=ArgAction::SetTrue)]
+    pub(crate) carve: bool,
+
+    #[arg(long = "wal-history")]
+    #[arg(
+        help = "Also search every version of pages in -wal and -journal files of the database, output includes frame and commit"
+    )]
+    #[arg(action=ArgAction::SetTrue)]
+    pub(crate) wal_history: bool,
+
+    #[arg(long = "engine")]
+    #[arg(help = indoc!("
+    How tables are read. raw reads tables from the database file directly,
+    tables it can't read are scanned with SQL"
+    ))]
+    #[arg(value_enum, default_value_t = ScanEngine::Sqlx)]
+    pub(crate) engine: ScanEngine,
+}
+
+fn parse_percent(value: &str) -> Result<f64, String> {
+    let percent = value
+        .trim_end_matches('%')
+        .parse::<f64>()
+        .map_err(|error| error.to_string())?;
+
+    check_percent(percent)
+}
+
+pub(crate) fn check_percent(percent: f64) -> Result<f64, String> {
+    if percent > 0.0 && percent <= 100.0 {
+        Ok(percent)
+    } else {
+        Err("percent must be greater than 0 and at most 100".into())
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct ConnectionArgs {
+    #[arg(long = "no-immutable")]
+    #[arg(help = "Don't open database as immutable to see changes of a live database")]
+    #[arg(action=ArgAction::SetTrue)]
+    pub(crate) no_immutable: bool,
+
+    #[arg(long = "include-wal")]
+    #[arg(help = "Read a copy of the database together with its write-ahead log")]
+    #[arg(action=ArgAction::SetTrue)]
+    pub(crate) include_wal: bool,
+
+    #[arg(long = "key", env = "SQLGREP_KEY", hide_env_values = true)]
+    #[arg(help = "SQLCipher passphrase or raw key as x'<hex>'")]
+    pub(crate) key: Option<String>,
+
+    #[arg(long = "key-file")]
+    #[arg(help = "Read SQLCipher passphrase or raw key from the file. Wins over --key")]
+    #[arg(value_name = "FILE")]
+    pub(crate) key_file: Option<PathBuf>,
+
+    #[arg(long = "cipher-compatibility")]
+    #[arg(help = "Open database created by SQLCipher of this major version")]
+    #[arg(value_name = "VERSION", value_parser = clap::value_parser!(u8).range(1..=4))]
+    pub(crate) cipher_compatibility: Option<u8>,
+
+    #[arg(long = "vfs")]
+    #[arg(help = "SQLite VFS to open database with")]
+    pub(crate) vfs: Option<String>,
+
+    #[arg(long = "from-dump")]
+    #[arg(help = "Load SQL dump into an in-memory database and scan it instead of DATABASE_URI")]
+    #[arg(value_name = "FILE")]
+    pub(crate) from_dump: Option<PathBuf>,
+
+    #[arg(long = "data-format")]
+    #[arg(help = indoc!("
+    Load DATABASE_URI as a table of an in-memory database.
+    Detected by .csv, .tsv, .tab, .ndjson and .jsonl extensions"
+    ))]
+    #[arg(value_enum, value_name = "FORMAT")]
+    pub(crate) data_format: Option<InputFormat>,
+
+    #[arg(long = "pragma")]
+    #[arg(
+        help = "PRAGMA to run on connection, e.g. cipher_compatibility=3. Can be used multiple times"
+    )]
+    #[arg(value_name = "NAME=VALUE", value_parser = parse_pragma)]
+    #[arg(action=ArgAction::Append)]
+    pub(crate) pragma: Vec<(String, String)>,
+}
+
+pub(crate) fn parse_pragma(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once('=')
+        .map(|(name, value)| (name.trim().to_owned(), value.trim().to_owned()))
+        .ok_or_else(|| "PRAGMA must be in NAME=VALUE format".into())
+}
+
+#[derive(Parser, Debug)]
+pub struct OutputArgs {
+    #[arg(short = 'o', long = "format")]
+    #[arg(help = "Output format")]
+    #[arg(value_enum, default_value_t = OutputFormat::Text)]
+    pub(crate) format: OutputFormat,
+}
+
+#[derive(Parser, Debug)]
+pub struct ConfigArgs {
+    #[arg(long = "config")]
+    #[arg(help = indoc!("
+    Configuration file to use instead of sqlgrep.toml
+    in the current directory or in the user config directory"
+    ))]
+    #[arg(value_name = "FILE")]
+    pub(crate) config: Option<PathBuf>,
+
+    #[arg(long = "profile")]
+    #[arg(help = "Named profile from the configuration file")]
+    #[arg(value_name = "NAME")]
+    pub(crate) profile: Option<String>,
+
+    #[arg(long = "no-config")]
+    #[arg(help = "Don't read configuration file")]
+    #[arg(action=ArgAction::SetTrue, conflicts_with_all = ["config", "profile"])]
+    pub(crate) no_config: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum InputFormat {
+    /// Comma separated values with a header row
+    Csv,
+    /// Tab separated values with a header row
+    Tsv,
+    /// JSON object on every line
+    Ndjson,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PatternEncoding {
+    /// Every encoding below
+    All,
+    /// Base64 with standard or URL-safe alphabet
+    Base64,
+    /// Hex digits in any case
+    Hex,
+    /// Percent-encoded
+    Url,
+    /// UTF-16 with both byte orders
+    Utf16,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScanEngine {
+    /// Read tables with SQL queries
+    Sqlx,
+    /// Read table b-trees from the database file
+    Raw,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// `<Table or Query>::<Row index>::<Column> => <Value>` lines
+    Text,
+    /// JSON object for every event on a separate line
+    Json,
+    /// CSV with source, row, column and value
+    Csv,
+}
+
+/// Parse duration in seconds with optional `ms`, `s`, `m` or `h` suffix
+pub(crate) fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, multiplier) = if let Some(number) = value.strip_suffix("ms") {
+        (number, 0.001)
+    } else if let Some(number) = value.strip_suffix('s') {
+        (number, 1.0)
+    } else if let Some(number) = value.strip_suffix('m') {
+        (number, 60.0)
+    } else if let Some(number) = value.strip_suffix('h') {
+        (number, 3600.0)
+    } else {
+        (value, 1.0)
+    };
+
+    let seconds = number
+        .trim()
+        .parse::<f64>()
+        .map_err(|error| error.to_string())?
+        * multiplier;
+
+    Duration::try_from_secs_f64(seconds).map_err(|error| error.to_string())
 }
 
 impl Verbose {
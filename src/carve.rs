@@ -0,0 +1,494 @@
+use std::borrow::Cow;
+use std::ops::Range;
+use std::path::Path;
+use std::time::Instant;
+
+use sqlparser::dialect::SQLiteDialect;
+
+use crate::cell_to_string::{Cell, CellMatch};
+use crate::error::SQLError;
+use crate::limits::Limits;
+use crate::pattern::Pattern;
+use crate::raw::map_file;
+use crate::search::{Event, Location, Match};
+use crate::select::escape_table_name;
+use crate::sqlite_file::{
+    decode_text, header_offset, parse_record, parse_record_body, read_u16, read_u32, read_varint,
+    serial_type_size, BtreePage, DatabaseFile, PageKind, Record, RecordValue, SchemaTree,
+    TextEncoding,
+};
+use crate::stats::SourceStats;
+
+/// Free space of pages which belongs to a source
+struct Region {
+    page: u32,
+    range: Range<usize>,
+    /// Region is a freeblock of a table leaf page which begins with a deleted row
+    freeblock: bool,
+}
+
+/// Recover deleted records from free space of a database file and match their values.
+///
+/// Free space is unallocated space and freeblocks of b-tree pages, and pages of the freelist.
+/// Source names start with `prefix`, the file is mapped into memory when it's `immutable`.
+pub(crate) fn carve_database(
+    path: &Path,
+    immutable: bool,
+    trees: &[SchemaTree],
+    prefix: &str,
+    pattern: &Pattern,
+    limits: &Limits,
+    emit: &mut impl FnMut(Event),
+) -> Result<Vec<(String, SourceStats)>, SQLError> {
+    // Database which isn't immutable can be changed by another process while it's read
+    let (map, copy);
+    let data: &[u8] = if immutable {
+        map = map_file(path)?;
+        &map
+    } else {
+        copy = std::fs::read(path)
+            .map_err(|error| SQLError::Io((format!("carve \"{}\"", path.display()), error)))?;
+        &copy
+    };
+    let file = DatabaseFile::new(data).map_err(|error| {
+        SQLError::Configuration(format!("can't carve \"{}\": {error}", path.display()))
+    })?;
+
+    let mut sources = vec![];
+
+    for tree in trees {
+        let regions = file
+            .btree_pages(tree.root)
+            .into_iter()
+            .flat_map(|number| {
+                let page = file.page(number).unwrap_or_default();
+                let Some(btree) = BtreePage::parse(page, header_offset(number)) else {
+                    return vec![];
+                };
+                let freeblock = btree.kind == PageKind::LeafTable;
+                let mut regions = vec![Region {
+                    page: number,
+                    range: btree.unallocated(page),
+                    freeblock: false,
+                }];
+                regions.extend(btree.freeblocks(page).into_iter().map(|range| Region {
+                    page: number,
+                    range,
+                    freeblock,
+                }));
+                regions
+            })
+            .collect::<Vec<_>>();
+
+        let id = format!(
+            "{prefix}Carved {}",
+            escape_table_name(&tree.name, &SQLiteDialect {})
+        );
+        let source = FileSource::start(id, &tree.columns, pattern, limits, emit);
+        sources.push(carve_regions(&file, Some(tree), source, regions, emit));
+    }
+
+    // Freed pages keep their content until they are reused
+    let (trunks, leaves) = file.freelist();
+    let mut regions = trunks
+        .into_iter()
+        .filter_map(|number| {
+            let page = file.page(number)?;
+            let count = read_u32(page, 4) as usize;
+            let start = 8usize
+                .saturating_add(count.saturating_mul(4))
+                .min(page.len());
+            Some(Region {
+                page: number,
+                range: start..page.len(),
+                freeblock: false,
+            })
+        })
+        .collect::<Vec<_>>();
+    regions.extend(leaves.into_iter().filter_map(|number| {
+        let page = file.page(number)?;
+        Some(Region {
+            page: number,
+            range: header_offset(number)..page.len(),
+            freeblock: false,
+        })
+    }));
+
+    let source = FileSource::start(
+        format!("{prefix}Carved freelist"),
+        &[],
+        pattern,
+        limits,
+        emit,
+    );
+    sources.push(carve_regions(&file, None, source, regions, emit));
+
+    Ok(sources)
+}
+
+fn carve_regions(
+    file: &DatabaseFile<'_>,
+    tree: Option<&SchemaTree>,
+    mut source: FileSource<'_>,
+    regions: Vec<Region>,
+    emit: &mut impl FnMut(Event),
+) -> (String, SourceStats) {
+    let encoding = file.header.encoding;
+
+    'regions: for region in regions {
+        let Some(page) = file.page(region.page) else {
+            continue;
+        };
+
+        let mut offset = region.range.start;
+        // Deleted cells of merged freeblocks follow each other
+        let mut cell = region.freeblock.then_some(offset);
+        while offset < region.range.end {
+            if !source.is_running(emit) {
+                break 'regions;
+            }
+
+            let data = &page[offset..region.range.end];
+            let record = match tree {
+                Some(tree) if cell == Some(offset) => {
+                    let record = rebuild_record(data, tree, encoding);
+                    // Freeblock header is never a part of a record
+                    if record.is_none() {
+                        offset += 4;
+                        continue;
+                    }
+                    record
+                }
+                _ => recover_record(data, encoding),
+            };
+            let Some((values, size)) = record else {
+                offset += 1;
+                continue;
+            };
+
+            let location = Location::new(region.page, offset);
+            if !source.match_record(values, location, None, emit, |_, _| true) {
+                break 'regions;
+            }
+
+            // Values of a recovered record aren't parsed as records again
+            offset += size;
+            if region.freeblock {
+                cell = Some(offset);
+            }
+        }
+    }
+
+    source.finish(emit)
+}
+
+/// Values of a record found at the beginning of data and size of the record
+fn recover_record(data: &[u8], encoding: TextEncoding) -> Option<(Vec<Cell<'_>>, usize)> {
+    let record = parse_record(data)?;
+    let values = checked_cells(&record, encoding)?;
+    Some((values, record.size.max(1)))
+}
+
+/// Values of a deleted row which cell begins the freeblock and size of the cell.
+///
+/// Freeblock header overwrites the first 4 bytes of the cell: payload size, rowid,
+/// record header size and the first serial type of short rows. Serial types of the other
+/// stored columns follow, the lost one is guessed from the freeblock size and column type.
+/// Cell which ends where the freeblock or a freeblock merged into it ends is preferred.
+fn rebuild_record<'d>(
+    data: &'d [u8],
+    tree: &SchemaTree,
+    encoding: TextEncoding,
+) -> Option<(Vec<Cell<'d>>, usize)> {
+    let count = tree.columns.len();
+    let declared = tree.types.first()?;
+
+    // Freeblock merged with the next one keeps its header with size of the rest
+    let mut ends = (4..data.len().saturating_sub(4))
+        .filter(|&end| usize::from(read_u16(data, end + 2)) == data.len() - end)
+        .collect::<Vec<_>>();
+    ends.push(data.len());
+
+    let mut candidates = vec![];
+
+    // Record header size is the last overwritten byte
+    candidates.extend(serial_types(data, 4, count));
+
+    // The first serial type is overwritten too, a long one keeps its last byte
+    for (start, lost) in [(4, None), (5, data.get(4).copied())] {
+        let Some((types, header_end)) = serial_types(data, start, count - 1) else {
+            continue;
+        };
+        let body = types
+            .iter()
+            .filter_map(|&serial_type| serial_type_size(serial_type))
+            .sum::<usize>();
+        let mut guessed = ends
+            .iter()
+            .filter_map(|end| end.checked_sub(header_end + body))
+            .flat_map(|size| guess_serial_types(size, declared, lost))
+            .collect::<Vec<_>>();
+        // Value of `INTEGER PRIMARY KEY` is rowid, the column is always NULL
+        if lost.is_none() {
+            guessed.push(0);
+        }
+        candidates.extend(guessed.into_iter().map(|serial_type| {
+            let types = std::iter::once(serial_type).chain(types.iter().copied());
+            (types.collect(), header_end)
+        }));
+    }
+
+    let mut found = None;
+    for (types, header_end) in candidates {
+        let Some(record) = parse_record_body(&types, &data[header_end..]) else {
+            continue;
+        };
+        let Some(values) = checked_cells(&record, encoding) else {
+            continue;
+        };
+        let size = header_end + record.size;
+        if ends.contains(&size) {
+            return Some((values, size));
+        }
+        found.get_or_insert((values, size));
+    }
+
+    found
+}
+
+/// Serial types read from the offset and offset of the end of them
+fn serial_types(data: &[u8], mut offset: usize, count: usize) -> Option<(Vec<u64>, usize)> {
+    let mut types = vec![];
+    for _ in 0..count {
+        let (serial_type, length) = read_varint(data.get(offset..)?)?;
+        serial_type_size(serial_type)?;
+        types.push(serial_type);
+        offset += length;
+    }
+    Some((types, offset))
+}
+
+/// Serial types of a value of the size, more likely ones for the declared column type go first.
+///
+/// `last` is the last byte of a two-byte serial type which first byte is lost.
+fn guess_serial_types(size: usize, declared: &str, last: Option<u8>) -> Vec<u64> {
+    let declared = declared.to_ascii_uppercase();
+    let has = |names: &[&str]| names.iter().any(|name| declared.contains(name));
+    let is_real = !has(&["INT"]) && has(&["REAL", "FLOA", "DOUB"]);
+    // Column affinity as SQLite determines it
+    let is_numeric =
+        has(&["INT"]) || !(has(&["CHAR", "CLOB", "TEXT", "BLOB"]) || declared.trim().is_empty());
+
+    let numbers = match (size, is_real) {
+        (0, _) => vec![0],
+        (1..=4, _) => vec![size as u64],
+        (6, _) => vec![5],
+        (8, true) => vec![7, 6],
+        (8, false) => vec![6, 7],
+        _ => vec![],
+    };
+    let strings = [13, 12].map(|kind| kind + 2 * size as u64);
+
+    match last {
+        // The whole serial type is lost, so it takes a single byte
+        None => {
+            let guessed = if is_numeric || size == 0 {
+                numbers.into_iter().chain(strings).collect::<Vec<_>>()
+            } else {
+                strings.into_iter().chain(numbers).collect()
+            };
+            guessed
+                .into_iter()
+                .filter(|&serial_type| serial_type < 0x80)
+                .collect()
+        }
+        Some(last) => strings
+            .into_iter()
+            .filter(|&serial_type| {
+                (0x80..0x4000).contains(&serial_type) && serial_type & 0x7f == u64::from(last)
+            })
+            .collect(),
+    }
+}
+
+/// Values of a record when it has text and all of it is valid,
+/// so random bytes and parts of other records are rarely recovered
+fn checked_cells<'r>(record: &Record<'r>, encoding: TextEncoding) -> Option<Vec<Cell<'r>>> {
+    let has_text = record
+        .values
+        .iter()
+        .any(|value| matches!(value, RecordValue::Text(text) if !text.is_empty()));
+    if !has_text {
+        return None;
+    }
+
+    let values = record_cells(record, encoding);
+    let is_valid = values.iter().all(|value| match value {
+        Cell::Value(value) => !value.chars().any(|c| c.is_control() && !c.is_whitespace()),
+        Cell::Null | Cell::Blob(_) => true,
+        Cell::Bytes(_) | Cell::Unknown => false,
+    });
+
+    is_valid.then_some(values)
+}
+
+/// Values of a record converted like cells returned by SQLite, text which can't be decoded is bytes
+pub(crate) fn record_cells<'r>(record: &Record<'r>, encoding: TextEncoding) -> Vec<Cell<'r>> {
+    record
+        .values
+        .iter()
+        .map(|value| match value {
+            RecordValue::Null => Cell::Null,
+            RecordValue::Integer(value) => Cell::Value(Cow::Owned(format!("{value}"))),
+            RecordValue::Real(value) => Cell::Value(Cow::Owned(format!("{value}"))),
+            RecordValue::Text(bytes) => match decode_text(bytes, encoding) {
+                Some(text) => Cell::Value(text),
+                None => Cell::Bytes(Cow::Borrowed(bytes)),
+            },
+            RecordValue::Blob(bytes) => Cell::Blob(Cow::Borrowed(bytes)),
+        })
+        .collect()
+}
+
+/// Source of records read from the database file directly
+pub(crate) struct FileSource<'c> {
+    id: String,
+    columns: &'c [String],
+    pattern: &'c Pattern,
+    limits: &'c Limits,
+    pub stats: SourceStats,
+    started: Instant,
+    deadline: Option<Instant>,
+}
+
+impl<'c> FileSource<'c> {
+    pub fn start(
+        id: String,
+        columns: &'c [String],
+        pattern: &'c Pattern,
+        limits: &'c Limits,
+        emit: &mut impl FnMut(Event),
+    ) -> Self {
+        emit(Event::SourceStart(id.clone()));
+
+        Self {
+            id,
+            columns,
+            pattern,
+            limits,
+            stats: SourceStats::default(),
+            started: Instant::now(),
+            deadline: limits.deadline(),
+        }
+    }
+
+    /// `false` when a limit is reached or the source is timed out
+    pub fn is_running(&mut self, emit: &mut impl FnMut(Event)) -> bool {
+        if self.limits.is_done(&self.stats) {
+            log::debug!("{}: limit reached", self.id);
+            return false;
+        }
+
+        if self
+            .deadline
+            .is_some_and(|deadline| deadline <= Instant::now())
+        {
+            emit(Event::Error(SQLError::Timeout(self.id.clone())));
+            self.stats.timed_out = true;
+            return false;
+        }
+
+        true
+    }
+
+    /// Match values of a record, `false` when no more matches are allowed.
+    ///
+    /// `is_new` is called with column index and value of every match before it's reported.
+    pub fn match_record(
+        &mut self,
+        values: Vec<Cell<'_>>,
+        location: Location,
+        rowid: Option<i64>,
+        emit: &mut impl FnMut(Event),
+        mut is_new: impl FnMut(usize, &str) -> bool,
+    ) -> bool {
+        let row = self.stats.rows;
+        self.stats.rows += 1;
+
+        for (index, value) in values.into_iter().enumerate() {
+            self.stats.cells += 1;
+            match value {
+                Cell::Null => {
+                    self.stats.skipped_null += 1;
+                    continue;
+                }
+                Cell::Blob(_) if !self.pattern.options().matches_blobs() => {
+                    self.stats.skipped_blob += 1;
+                    continue;
+                }
+                Cell::Unknown => {
+                    self.stats.skipped_unknown += 1;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let Some(value) = value.find(self.pattern) else {
+                continue;
+            };
+            if !is_new(index, &value.value) {
+                continue;
+            }
+            let location = Some(location.clone());
+            if !self.found(row, index, value, location, rowid, emit) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Report a matched value, `false` when no more matches are allowed
+    pub fn found(
+        &mut self,
+        row: u64,
+        index: usize,
+        value: CellMatch,
+        location: Option<Location>,
+        rowid: Option<i64>,
+        emit: &mut impl FnMut(Event),
+    ) -> bool {
+        if !self.limits.acquire_match(&self.stats) {
+            return false;
+        }
+
+        let column = self
+            .columns
+            .get(index)
+            .cloned()
+            .unwrap_or_else(|| format!("#{index}"));
+        emit(Event::Match(Match {
+            source: self.id.clone(),
+            row,
+            column,
+            value: value.value,
+            offset: value.offset,
+            encoding: value.encoding,
+            decoding: value.decoding,
+            json_path: value.json_path,
+            protobuf_path: value.protobuf_path,
+            location,
+            rowid,
+        }));
+        self.stats.matches += 1;
+
+        true
+    }
+
+    pub fn finish(mut self, emit: &mut impl FnMut(Event)) -> (String, SourceStats) {
+        self.stats.elapsed = self.started.elapsed();
+        emit(Event::SourceEnd((self.id.clone(), self.stats.clone())));
+
+        (self.id, self.stats)
+    }
+}
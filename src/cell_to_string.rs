@@ -1,70 +1,362 @@
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::ops::Range;
+
 use sqlx::sqlite::SqliteValueRef;
 use sqlx::Decode;
 use sqlx::Sqlite;
 use sqlx::Type;
 use sqlx::ValueRef;
 
-pub(crate) fn sqlite_cell_to_string(value_ref: SqliteValueRef) -> Result<Option<String>, String> {
+use crate::decode::{find_decoded, Decoding};
+use crate::encodings::Encoding;
+use crate::json_cells::{find_in_document, parse_document};
+use crate::pattern::Pattern;
+use crate::protobuf::find_in_protobuf;
+
+/// Cell converted to a string or a reason it was skipped
+pub(crate) enum Cell<'r> {
+    Value(Cow<'r, str>),
+    /// TEXT which isn't valid in the database encoding
+    Bytes(Cow<'r, [u8]>),
+    Null,
+    Blob(Cow<'r, [u8]>),
+    Unknown,
+}
+
+/// Matched cell rendered to print
+pub(crate) struct CellMatch {
+    pub value: String,
+    /// Offset of the match in BLOB for binary and encoded patterns
+    pub offset: Option<usize>,
+    /// Encoding of the value found by encoded pattern
+    pub encoding: Option<Encoding>,
+    /// Decodings applied to the cell value to get the matched value
+    pub decoding: Vec<Decoding>,
+    /// Path of the matched key or value inside JSON document
+    pub json_path: Option<String>,
+    /// Field numbers of the matched field inside protobuf message
+    pub protobuf_path: Option<String>,
+}
+
+impl CellMatch {
+    fn new(value: String) -> Self {
+        Self {
+            value,
+            offset: None,
+            encoding: None,
+            decoding: vec![],
+            json_path: None,
+            protobuf_path: None,
+        }
+    }
+}
+
+/// Bytes of BLOB around a binary match printed in hexdump
+const HEXDUMP_CONTEXT: usize = 8;
+/// Bytes of a binary match printed in hexdump, longer match is cut
+const HEXDUMP_MATCH: usize = 32;
+
+impl Cell<'_> {
+    /// Value rendered to print when it matches the pattern.
+    ///
+    /// BLOB is matched only when it's enabled in pattern options,
+    /// binary and encoded patterns print hexdump around the match in BLOB.
+    /// Decoded layers of the value are matched when decoding is enabled in pattern options.
+    /// Keys and values of JSON documents are matched separately when JSON cells are enabled,
+    /// so are fields of BLOB protobuf messages when protobuf is enabled.
+    pub fn find(&self, pattern: &Pattern) -> Option<CellMatch> {
+        self.find_layer(pattern)
+            .or_else(|| find_decoded(self, pattern))
+    }
+
+    /// Match the value as is, JSON documents and protobuf messages are matched by fields if enabled
+    pub(crate) fn find_layer(&self, pattern: &Pattern) -> Option<CellMatch> {
+        let options = pattern.options();
+        let json_paths = !options.json_paths.is_empty();
+        if let (Cell::Value(value), true) = (self, options.json_cells || json_paths) {
+            if let Some(document) = parse_document(value) {
+                return find_in_document(&document, pattern);
+            }
+        }
+        // Only JSON documents have the paths, other cells never match them
+        if json_paths {
+            return None;
+        }
+        if let (Cell::Blob(value), true) = (self, options.protobuf) {
+            if let Some(found) = find_in_protobuf(value, pattern) {
+                return found;
+            }
+        }
+
+        self.find_value(pattern)
+    }
+
+    /// Match the whole value
+    pub(crate) fn find_value(&self, pattern: &Pattern) -> Option<CellMatch> {
+        if let Pattern::Encoded(_) = pattern {
+            return self.find_encoded(pattern);
+        }
+
+        match self {
+            Cell::Value(value) => pattern
+                .is_match(value)
+                .then(|| CellMatch::new(value.as_ref().to_owned())),
+            Cell::Bytes(value) => pattern
+                .is_match_bytes(value)
+                .then(|| CellMatch::new(escape_bytes(value))),
+            Cell::Blob(value) if matches!(pattern, Pattern::Binary(_)) => {
+                let range = pattern.find_binary(value)?;
+                Some(CellMatch {
+                    offset: Some(range.start),
+                    ..CellMatch::new(hexdump(value, range))
+                })
+            }
+            Cell::Blob(value) if pattern.options().blobs => pattern
+                .is_match_bytes(value)
+                .then(|| CellMatch::new(escape_bytes(value))),
+            Cell::Null | Cell::Blob(_) | Cell::Unknown => None,
+        }
+    }
+
+    fn find_encoded(&self, pattern: &Pattern) -> Option<CellMatch> {
+        let (value, encoding) = match self {
+            Cell::Value(value) => {
+                let (_, encoding) = pattern.find_encoded(value.as_bytes())?;
+                (value.as_ref().to_owned(), encoding)
+            }
+            Cell::Bytes(value) => {
+                let (_, encoding) = pattern.find_encoded(value)?;
+                (escape_bytes(value), encoding)
+            }
+            Cell::Blob(value) => {
+                let (range, encoding) = pattern.find_encoded(value)?;
+                return Some(CellMatch {
+                    offset: Some(range.start),
+                    encoding: Some(encoding),
+                    ..CellMatch::new(hexdump(value, range))
+                });
+            }
+            Cell::Null | Cell::Unknown => return None,
+        };
+
+        Some(CellMatch {
+            encoding: Some(encoding),
+            ..CellMatch::new(value)
+        })
+    }
+
+    /// The same cell borrowing its value
+    pub fn borrowed(&self) -> Cell<'_> {
+        match self {
+            Cell::Value(value) => Cell::Value(Cow::Borrowed(value)),
+            Cell::Bytes(value) => Cell::Bytes(Cow::Borrowed(value)),
+            Cell::Null => Cell::Null,
+            Cell::Blob(value) => Cell::Blob(Cow::Borrowed(value)),
+            Cell::Unknown => Cell::Unknown,
+        }
+    }
+
+    /// Copy borrowed value to keep it after the row is gone
+    pub fn into_owned(self) -> Cell<'static> {
+        match self {
+            Cell::Value(value) => Cell::Value(Cow::Owned(value.into_owned())),
+            Cell::Bytes(value) => Cell::Bytes(Cow::Owned(value.into_owned())),
+            Cell::Null => Cell::Null,
+            Cell::Blob(value) => Cell::Blob(Cow::Owned(value.into_owned())),
+            Cell::Unknown => Cell::Unknown,
+        }
+    }
+}
+
+/// Convert a cell to a string.
+///
+/// Text is borrowed from the row, other values are formatted into `buffer`,
+/// so the cell is only copied when it has to be kept.
+pub(crate) fn sqlite_cell_to_string<'r>(
+    value_ref: SqliteValueRef<'r>,
+    buffer: &'r mut String,
+) -> Result<Cell<'r>, String> {
     if value_ref.is_null() {
-        return Ok(None);
+        return Ok(Cell::Null);
     }
 
     let type_info = value_ref.type_info().into_owned();
+    buffer.clear();
 
-    // TEXT
-    if <String as Type<Sqlite>>::compatible(&type_info) {
+    // TEXT, SQLite doesn't check it's valid UTF-8
+    if <&str as Type<Sqlite>>::compatible(&type_info) {
         let value =
-            <String as Decode<Sqlite>>::decode(value_ref).map_err(|value| value.to_string())?;
-        return Ok(Some(value));
+            <&[u8] as Decode<Sqlite>>::decode(value_ref).map_err(|value| value.to_string())?;
+        return Ok(match std::str::from_utf8(value) {
+            Ok(value) => Cell::Value(Cow::Borrowed(value)),
+            Err(_) => Cell::Bytes(Cow::Borrowed(value)),
+        });
     }
 
     // // INTEGER, INT4
     if <i64 as Type<Sqlite>>::compatible(&type_info) {
         let value =
             <i64 as Decode<Sqlite>>::decode(value_ref).map_err(|value| value.to_string())?;
-        return Ok(Some(format!("{value}")));
+        let _ = write!(buffer, "{value}");
+        return Ok(Cell::Value(Cow::Borrowed(buffer)));
     }
     // REAL
     if <f64 as Type<Sqlite>>::compatible(&type_info) {
         let value =
             <f64 as Decode<Sqlite>>::decode(value_ref).map_err(|value| value.to_string())?;
-        return Ok(Some(format!("{value}")));
+        let _ = write!(buffer, "{value}");
+        return Ok(Cell::Value(Cow::Borrowed(buffer)));
     }
     // BOOL?
     if <bool as Type<Sqlite>>::compatible(&type_info) {
         let value =
             <bool as Decode<Sqlite>>::decode(value_ref).map_err(|value| value.to_string())?;
-        return Ok(Some(format!("{value}")));
+        let _ = write!(buffer, "{value}");
+        return Ok(Cell::Value(Cow::Borrowed(buffer)));
     }
     // DateTime
     if <chrono::DateTime<chrono::Local> as Type<Sqlite>>::compatible(&type_info) {
         let value = <chrono::DateTime<chrono::Local> as Decode<Sqlite>>::decode(value_ref)
             .map_err(|value| value.to_string())?;
-        return Ok(Some(value.to_rfc3339()));
+        return Ok(Cell::Value(Cow::Owned(value.to_rfc3339())));
     }
     // Date
     if <chrono::NaiveDate as Type<Sqlite>>::compatible(&type_info) {
         let value = <chrono::NaiveDate as Decode<Sqlite>>::decode(value_ref)
             .map_err(|value| value.to_string())?;
-        return Ok(Some(value.format("%Y-%m-%d").to_string()));
+        let _ = write!(buffer, "{}", value.format("%Y-%m-%d"));
+        return Ok(Cell::Value(Cow::Borrowed(buffer)));
     }
     // Time
     if <chrono::NaiveTime as Type<Sqlite>>::compatible(&type_info) {
         let value = <chrono::NaiveTime as Decode<Sqlite>>::decode(value_ref)
             .map_err(|value| value.to_string())?;
-        return Ok(Some(value.format("%H:%M:%S").to_string()));
+        let _ = write!(buffer, "{}", value.format("%H:%M:%S"));
+        return Ok(Cell::Value(Cow::Borrowed(buffer)));
     }
 
     // BLOB
-    if <Vec<u8> as Type<Sqlite>>::compatible(&type_info) {
-        // ignore this type for now.
-        // let _  = <Vec<u8> as Decode<Sqlite>>::decode(value_ref).map_err(_format)?;
-        // TODO: add option to try decode as zlib string
-        // TODO: add option to try decode as UTF-8 bytes
-        // TODO: add option to try decode as UTF-16 bytes?
+    if <&[u8] as Type<Sqlite>>::compatible(&type_info) {
         // TODO: add option to try decode as UUID
-        return Ok(None);
+        let value =
+            <&[u8] as Decode<Sqlite>>::decode(value_ref).map_err(|value| value.to_string())?;
+        return Ok(Cell::Blob(Cow::Borrowed(value)));
     }
 
-    Err("Unknown type".into())
+    Ok(Cell::Unknown)
+}
+
+/// Render bytes to print them: valid UTF-8 is kept, other bytes and control characters
+/// are escaped as `\xNN`
+pub(crate) fn escape_bytes(value: &[u8]) -> String {
+    let mut result = String::with_capacity(value.len());
+    for chunk in value.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if c.is_control() && !matches!(c, '\t' | '\n' | '\r') {
+                let _ = write!(result, "\\x{:02X}", c as u32);
+            } else {
+                result.push(c);
+            }
+        }
+        for byte in chunk.invalid() {
+            let _ = write!(result, "\\x{byte:02X}");
+        }
+    }
+    result
+}
+
+/// Render bytes around a match as hex with matched bytes in brackets and ASCII after them,
+/// e.g. `73 65 63 [DE AD BE EF] 72 65 74  |sec....ret|`
+fn hexdump(value: &[u8], range: Range<usize>) -> String {
+    let start = range.start.saturating_sub(HEXDUMP_CONTEXT);
+    let cut = range.len() > HEXDUMP_MATCH;
+    let matched = range.start..range.end.min(range.start + HEXDUMP_MATCH);
+    let end = if cut {
+        matched.end
+    } else {
+        (range.end + HEXDUMP_CONTEXT).min(value.len())
+    };
+
+    let mut hex = value[start..end]
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>();
+    if matched.is_empty() {
+        hex.insert(matched.start - start, "[]".into());
+    } else {
+        hex[matched.start - start].insert(0, '[');
+        hex[matched.end - 1 - start].push_str(if cut { " ..]" } else { "]" });
+    }
+    if start > 0 {
+        hex.insert(0, "..".into());
+    }
+    if end < value.len() && !cut {
+        hex.push("..".into());
+    }
+
+    let mut result = hex.join(" ");
+    result.push_str("  |");
+    for byte in &value[start..end] {
+        result.push(if byte.is_ascii_graphic() || *byte == b' ' {
+            char::from(*byte)
+        } else {
+            '.'
+        });
+    }
+    result.push('|');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::{PatternKind, PatternOptions};
+
+    #[test]
+    fn invalid_utf8_and_control_characters_are_escaped() {
+        assert_eq!(escape_bytes(b"plain text"), "plain text");
+        assert_eq!(escape_bytes("tab\tnew\nline".as_bytes()), "tab\tnew\nline");
+        assert_eq!(escape_bytes(b"\x00bell\x07"), "\\x00bell\\x07");
+        assert_eq!(escape_bytes("ß\u{85}".as_bytes()), "ß\\x85");
+        assert_eq!(escape_bytes(b"\xff\xfeok\xc3"), "\\xFF\\xFEok\\xC3");
+    }
+
+    #[test]
+    fn hexdump_shows_context_around_match() {
+        let value = b"secret\xde\xad\xbe\xefrest of the blob";
+
+        assert_eq!(
+            hexdump(value, 6..10),
+            "73 65 63 72 65 74 [DE AD BE EF] 72 65 73 74 20 6F 66 20 ..  |secret....rest of |"
+        );
+        assert_eq!(
+            hexdump(&value[4..14], 2..6),
+            "65 74 [DE AD BE EF] 72 65 73 74  |et....rest|"
+        );
+        assert_eq!(hexdump(b"abc", 1..1), "61 [] 62 63  |abc|");
+    }
+
+    #[test]
+    fn hexdump_cuts_long_match() {
+        let value = [[b'A'; 10], [b'B'; 10], [b'B'; 10], [b'B'; 10], [b'C'; 10]].concat();
+        let dump = hexdump(&value, 5..45);
+
+        assert!(dump.starts_with("41 41 41 41 41 [41 41"), "{dump}");
+        assert!(dump.ends_with(&format!(" 42 ..]  |{}{}|", "A".repeat(10), "B".repeat(27))));
+    }
+
+    #[test]
+    fn binary_pattern_matches_blob_only() {
+        let pattern = Pattern::new("DEAD", &PatternKind::Hex, PatternOptions::default()).unwrap();
+
+        let found = Cell::Blob(Cow::Borrowed(b"\x01\xde\xad"))
+            .find(&pattern)
+            .unwrap();
+        assert_eq!(found.offset, Some(1));
+        assert_eq!(found.value, "01 [DE AD]  |...|");
+        assert!(Cell::Bytes(Cow::Borrowed(b"\xde\xad"))
+            .find(&pattern)
+            .is_none());
+    }
 }
@@ -0,0 +1,534 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use clap::parser::ValueSource;
+use clap::{Arg, ArgMatches, CommandFactory as _, ValueEnum as _};
+use serde::Deserialize;
+use sqlgrep::SQLError;
+
+use crate::args::{
+    check_percent, parse_duration, parse_pragma, Args, OutputFormat, PatternEncoding, ScanEngine,
+};
+
+const CONFIG_FILE_NAME: &str = "sqlgrep.toml";
+
+/// Configuration file with defaults and named profiles
+///
+/// ```toml
+/// max-errors = 10
+///
+/// [profile.chrome-history]
+/// table = ["urls", "visits"]
+/// prefilter = true
+/// ```
+#[derive(Debug, Default)]
+struct Config {
+    /// Top level keys
+    defaults: ConfigOptions,
+    profile: HashMap<String, ConfigOptions>,
+}
+
+/// Options named after long command line options
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct ConfigOptions {
+    pattern_fixed: Option<bool>,
+    hex_pattern: Option<bool>,
+    byte_regex: Option<bool>,
+    encodings: Option<Vec<String>>,
+    decode_depth: Option<usize>,
+    json_cells: Option<bool>,
+    json_path: Option<Vec<String>>,
+    protobuf: Option<bool>,
+    pattern_whole: Option<bool>,
+    pattern_case_insensitive: Option<bool>,
+    pattern_blobs: Option<bool>,
+    table: Option<Vec<String>>,
+    sql: Option<Vec<String>>,
+    ignore: Option<bool>,
+    prefilter: Option<bool>,
+    jobs: Option<u32>,
+    max_count: Option<u64>,
+    max_total_count: Option<u64>,
+    max_rows: Option<u64>,
+    sample: Option<f64>,
+    timeout: Option<String>,
+    total_timeout: Option<String>,
+    max_errors: Option<u64>,
+    sort: Option<bool>,
+    no_progress: Option<bool>,
+    stats: Option<bool>,
+    carve: Option<bool>,
+    wal_history: Option<bool>,
+    engine: Option<String>,
+    no_immutable: Option<bool>,
+    include_wal: Option<bool>,
+    key_file: Option<PathBuf>,
+    cipher_compatibility: Option<u8>,
+    vfs: Option<String>,
+    pragma: Option<Vec<String>>,
+    format: Option<String>,
+}
+
+impl ConfigOptions {
+    /// Options from `other` override options from `self`
+    fn merge(self, other: Self) -> Self {
+        Self {
+            pattern_fixed: other.pattern_fixed.or(self.pattern_fixed),
+            hex_pattern: other.hex_pattern.or(self.hex_pattern),
+            byte_regex: other.byte_regex.or(self.byte_regex),
+            encodings: other.encodings.or(self.encodings),
+            decode_depth: other.decode_depth.or(self.decode_depth),
+            json_cells: other.json_cells.or(self.json_cells),
+            json_path: other.json_path.or(self.json_path),
+            protobuf: other.protobuf.or(self.protobuf),
+            pattern_whole: other.pattern_whole.or(self.pattern_whole),
+            pattern_case_insensitive: other
+                .pattern_case_insensitive
+                .or(self.pattern_case_insensitive),
+            pattern_blobs: other.pattern_blobs.or(self.pattern_blobs),
+            table: other.table.or(self.table),
+            sql: other.sql.or(self.sql),
+            ignore: other.ignore.or(self.ignore),
+            prefilter: other.prefilter.or(self.prefilter),
+            jobs: other.jobs.or(self.jobs),
+            max_count: other.max_count.or(self.max_count),
+            max_total_count: other.max_total_count.or(self.max_total_count),
+            max_rows: other.max_rows.or(self.max_rows),
+            sample: other.sample.or(self.sample),
+            timeout: other.timeout.or(self.timeout),
+            total_timeout: other.total_timeout.or(self.total_timeout),
+            max_errors: other.max_errors.or(self.max_errors),
+            sort: other.sort.or(self.sort),
+            no_progress: other.no_progress.or(self.no_progress),
+            stats: other.stats.or(self.stats),
+            carve: other.carve.or(self.carve),
+            wal_history: other.wal_history.or(self.wal_history),
+            engine: other.engine.or(self.engine),
+            no_immutable: other.no_immutable.or(self.no_immutable),
+            include_wal: other.include_wal.or(self.include_wal),
+            key_file: other.key_file.or(self.key_file),
+            cipher_compatibility: other.cipher_compatibility.or(self.cipher_compatibility),
+            vfs: other.vfs.or(self.vfs),
+            pragma: other.pragma.or(self.pragma),
+            format: other.format.or(self.format),
+        }
+    }
+}
+
+/// Set option from configuration unless it's passed on command line.
+///
+/// Options which are changed by configuration are added to `configured`.
+macro_rules! merge_option {
+    ($configured:expr, $matches:expr, $id:literal, $target:expr, $value:expr) => {
+        if let Some(value) = $value {
+            if $matches.value_source($id) != Some(ValueSource::CommandLine) {
+                if value != $target {
+                    $configured.insert($id);
+                }
+                $target = value;
+            }
+        }
+    };
+}
+
+/// Read configuration file and profile and merge them into arguments.
+///
+/// Options passed on command line win over configuration.
+pub(crate) fn apply_config(args: &mut Args, matches: &ArgMatches) -> Result<(), SQLError> {
+    if args.config.no_config {
+        return Ok(());
+    }
+
+    let path = match &args.config.config {
+        Some(path) => Some(path.clone()),
+        None => find_config(),
+    };
+
+    let Some(path) = path else {
+        return match &args.config.profile {
+            Some(profile) => Err(SQLError::Configuration(format!(
+                "profile \"{profile}\" is requested, but no {CONFIG_FILE_NAME} is found"
+            ))),
+            None => Ok(()),
+        };
+    };
+
+    log::debug!("Using configuration file {}", path.display());
+
+    let config = read_config(&path)?;
+
+    let options = match &args.config.profile {
+        None => config.defaults,
+        Some(name) => {
+            let mut profiles = config.profile;
+            let profile = profiles.remove(name).ok_or_else(|| {
+                SQLError::Configuration(format!(
+                    "profile \"{name}\" isn't found in {}",
+                    path.display()
+                ))
+            })?;
+            config.defaults.merge(profile)
+        }
+    };
+
+    apply_options(args, matches, options)
+        .and_then(|configured| check_conflicts(matches, &configured))
+        .map_err(|error| SQLError::Configuration(format!("{error} in {}", path.display())))
+}
+
+/// Check configured options with the same conflicts clap checks on command line
+fn check_conflicts(matches: &ArgMatches, configured: &HashSet<&str>) -> Result<(), String> {
+    let command = Args::command();
+    let is_set = |id: &str| {
+        configured.contains(id)
+            || matches!(
+                matches.value_source(id),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+    };
+    let name = |arg: &Arg| match arg.get_long() {
+        Some(long) => format!("--{long}"),
+        None => arg.get_id().to_string(),
+    };
+
+    for arg in command.get_arguments() {
+        let id = arg.get_id().as_str();
+        for other in command.get_arg_conflicts_with(arg) {
+            let other_id = other.get_id().as_str();
+            let is_configured = configured.contains(id) || configured.contains(other_id);
+            if is_configured && is_set(id) && is_set(other_id) {
+                return Err(format!("{} can't be used with {}", name(arg), name(other)));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Configuration in the current directory is preferred over user configuration
+fn find_config() -> Option<PathBuf> {
+    let local = PathBuf::from(CONFIG_FILE_NAME);
+    if local.is_file() {
+        return Some(local);
+    }
+
+    user_config_dir()
+        .map(|dir| dir.join("sqlgrep").join(CONFIG_FILE_NAME))
+        .filter(|path| path.is_file())
+}
+
+/// `$XDG_CONFIG_HOME` or `~/.config` on every platform but Windows, where it's `%APPDATA%`
+fn user_config_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        return dirs::config_dir();
+    }
+
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")))
+}
+
+fn read_config(path: &Path) -> Result<Config, SQLError> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|error| SQLError::Io((format!("read \"{}\"", path.display()), error)))?;
+
+    let error = |error: toml::de::Error| {
+        SQLError::Configuration(format!("unable to parse {}: {error}", path.display()))
+    };
+
+    // Flattened defaults would accept unknown keys, so profiles are taken out first
+    let mut defaults = toml::from_str::<toml::Table>(&content).map_err(error)?;
+    let profile = match defaults.remove("profile") {
+        Some(profile) => profile.try_into().map_err(error)?,
+        None => HashMap::new(),
+    };
+
+    Ok(Config {
+        defaults: toml::Value::Table(defaults).try_into().map_err(error)?,
+        profile,
+    })
+}
+
+/// Merge options into arguments, returns options changed by configuration
+fn apply_options(
+    args: &mut Args,
+    matches: &ArgMatches,
+    options: ConfigOptions,
+) -> Result<HashSet<&'static str>, String> {
+    let mut configured = HashSet::new();
+
+    merge_option!(
+        configured,
+        matches,
+        "fixed",
+        args.pattern.fixed,
+        options.pattern_fixed
+    );
+    merge_option!(
+        configured,
+        matches,
+        "hex",
+        args.pattern.hex,
+        options.hex_pattern
+    );
+    merge_option!(
+        configured,
+        matches,
+        "byte_regex",
+        args.pattern.byte_regex,
+        options.byte_regex
+    );
+    let encodings = options
+        .encodings
+        .map(|encodings| {
+            encodings
+                .iter()
+                .map(|encoding| PatternEncoding::from_str(encoding, true))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()
+        .map_err(|error| format!("encodings: {error}"))?;
+    merge_option!(
+        configured,
+        matches,
+        "encodings",
+        args.pattern.encodings,
+        encodings
+    );
+    merge_option!(
+        configured,
+        matches,
+        "decode_depth",
+        args.pattern.decode_depth,
+        options.decode_depth
+    );
+    merge_option!(
+        configured,
+        matches,
+        "json_cells",
+        args.pattern.json_cells,
+        options.json_cells
+    );
+    merge_option!(
+        configured,
+        matches,
+        "json_path",
+        args.pattern.json_path,
+        options.json_path
+    );
+    merge_option!(
+        configured,
+        matches,
+        "protobuf",
+        args.pattern.protobuf,
+        options.protobuf
+    );
+    merge_option!(
+        configured,
+        matches,
+        "whole_string",
+        args.pattern.whole_string,
+        options.pattern_whole
+    );
+    merge_option!(
+        configured,
+        matches,
+        "case_insensitive",
+        args.pattern.case_insensitive,
+        options.pattern_case_insensitive
+    );
+    merge_option!(
+        configured,
+        matches,
+        "blobs",
+        args.pattern.blobs,
+        options.pattern_blobs
+    );
+
+    merge_option!(
+        configured,
+        matches,
+        "table",
+        args.query.table,
+        options.table
+    );
+    merge_option!(configured, matches, "query", args.query.query, options.sql);
+    merge_option!(
+        configured,
+        matches,
+        "ignore_non_readonly",
+        args.query.ignore_non_readonly,
+        options.ignore
+    );
+    merge_option!(
+        configured,
+        matches,
+        "prefilter",
+        args.query.prefilter,
+        options.prefilter
+    );
+
+    if options.jobs == Some(0) {
+        return Err("jobs must be at least 1".into());
+    }
+    let sample = options
+        .sample
+        .map(check_percent)
+        .transpose()
+        .map_err(|error| format!("sample {error}"))?;
+    let timeout = options
+        .timeout
+        .as_deref()
+        .map(parse_duration)
+        .transpose()
+        .map_err(|error| format!("timeout: {error}"))?;
+    let total_timeout = options
+        .total_timeout
+        .as_deref()
+        .map(parse_duration)
+        .transpose()
+        .map_err(|error| format!("total-timeout: {error}"))?;
+
+    merge_option!(configured, matches, "jobs", args.scan.jobs, options.jobs);
+    merge_option!(
+        configured,
+        matches,
+        "max_count",
+        args.scan.max_count,
+        options.max_count.map(Some)
+    );
+    merge_option!(
+        configured,
+        matches,
+        "max_total_count",
+        args.scan.max_total_count,
+        options.max_total_count.map(Some)
+    );
+    merge_option!(
+        configured,
+        matches,
+        "max_rows",
+        args.scan.max_rows,
+        options.max_rows.map(Some)
+    );
+    merge_option!(
+        configured,
+        matches,
+        "sample",
+        args.scan.sample,
+        sample.map(Some)
+    );
+    merge_option!(
+        configured,
+        matches,
+        "timeout",
+        args.scan.timeout,
+        timeout.map(Some)
+    );
+    merge_option!(
+        configured,
+        matches,
+        "total_timeout",
+        args.scan.total_timeout,
+        total_timeout.map(Some)
+    );
+    merge_option!(
+        configured,
+        matches,
+        "max_errors",
+        args.scan.max_errors,
+        options.max_errors
+    );
+    merge_option!(configured, matches, "sort", args.scan.sort, options.sort);
+    merge_option!(
+        configured,
+        matches,
+        "no_progress",
+        args.scan.no_progress,
+        options.no_progress
+    );
+    merge_option!(configured, matches, "stats", args.scan.stats, options.stats);
+    merge_option!(configured, matches, "carve", args.scan.carve, options.carve);
+    merge_option!(
+        configured,
+        matches,
+        "wal_history",
+        args.scan.wal_history,
+        options.wal_history
+    );
+    let engine = options
+        .engine
+        .as_deref()
+        .map(|engine| ScanEngine::from_str(engine, true))
+        .transpose()
+        .map_err(|error| format!("engine: {error}"))?;
+    merge_option!(configured, matches, "engine", args.scan.engine, engine);
+
+    merge_option!(
+        configured,
+        matches,
+        "no_immutable",
+        args.connection.no_immutable,
+        options.no_immutable
+    );
+    merge_option!(
+        configured,
+        matches,
+        "include_wal",
+        args.connection.include_wal,
+        options.include_wal
+    );
+    // Key passed on command line or in environment wins over configured key file
+    if args.connection.key.is_none() {
+        merge_option!(
+            configured,
+            matches,
+            "key_file",
+            args.connection.key_file,
+            options.key_file.map(Some)
+        );
+    }
+    if options
+        .cipher_compatibility
+        .is_some_and(|version| !(1..=4).contains(&version))
+    {
+        return Err("cipher-compatibility must be from 1 to 4".into());
+    }
+    merge_option!(
+        configured,
+        matches,
+        "cipher_compatibility",
+        args.connection.cipher_compatibility,
+        options.cipher_compatibility.map(Some)
+    );
+    merge_option!(
+        configured,
+        matches,
+        "vfs",
+        args.connection.vfs,
+        options.vfs.map(Some)
+    );
+    let pragma = options
+        .pragma
+        .map(|pragmas| pragmas.iter().map(|pragma| parse_pragma(pragma)).collect())
+        .transpose()?;
+    merge_option!(
+        configured,
+        matches,
+        "pragma",
+        args.connection.pragma,
+        pragma
+    );
+
+    let format = options
+        .format
+        .as_deref()
+        .map(|format| OutputFormat::from_str(format, true))
+        .transpose()
+        .map_err(|error| format!("format: {error}"))?;
+    merge_option!(configured, matches, "format", args.output.format, format);
+
+    Ok(configured)
+}
@@ -0,0 +1,445 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use percent_encoding::percent_decode_str;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection};
+use sqlx::ConnectOptions as _;
+use tempfile::TempDir;
+
+use crate::archive::extract_databases;
+use crate::error::SQLError;
+use crate::import::DataFormat;
+
+/// Options of SQLite connection.
+///
+/// Every option can be set in database URI query string as well, e.g.
+/// `sqlite://app.db?immutable=0&include_wal=1&pragma.cipher_compatibility=3`.
+/// Options set here win over the URI.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionOptions {
+    /// Open database as immutable, so locks and write-ahead log are ignored.
+    ///
+    /// Database is immutable when it's not set.
+    pub immutable: Option<bool>,
+    /// Read a copy of the database together with its write-ahead log
+    pub include_wal: Option<bool>,
+    /// SQLCipher key
+    pub key: Option<CipherKey>,
+    /// SQLCipher major version to open database created by it
+    pub cipher_compatibility: Option<u8>,
+    /// SQLite VFS to open database with
+    pub vfs: Option<String>,
+    /// PRAGMAs to run on every connection as name and value
+    pub pragmas: Vec<(String, String)>,
+    /// Database is a SQL dump to load into memory.
+    ///
+    /// Files with `.sql` extension are dumps when it's not set.
+    pub from_dump: Option<bool>,
+    /// Database is a data file to load into memory as a table.
+    ///
+    /// Format is detected by file extension when it's not set.
+    pub data_format: Option<DataFormat>,
+}
+
+impl ConnectionOptions {
+    /// Options from `self` override options from `other`
+    fn merge(self, other: Self) -> Self {
+        let mut pragmas = other.pragmas;
+        pragmas.extend(self.pragmas);
+
+        Self {
+            immutable: self.immutable.or(other.immutable),
+            include_wal: self.include_wal.or(other.include_wal),
+            key: self.key.or(other.key),
+            cipher_compatibility: self.cipher_compatibility.or(other.cipher_compatibility),
+            vfs: self.vfs.or(other.vfs),
+            pragmas,
+            from_dump: self.from_dump.or(other.from_dump),
+            data_format: self.data_format.or(other.data_format),
+        }
+    }
+}
+
+/// SQLCipher key
+#[derive(Clone, Debug)]
+pub enum CipherKey {
+    /// Passphrase to derive key from
+    Passphrase(String),
+    /// Raw key as hex digits, optionally followed by salt
+    Raw(String),
+}
+
+impl CipherKey {
+    /// Parse key in SQLCipher format: `x'<hex>'` is a raw key, anything else is a passphrase
+    pub fn parse(key: &str) -> Result<Self, SQLError> {
+        let Some(hex) = key
+            .strip_prefix("x'")
+            .or_else(|| key.strip_prefix("X'"))
+            .and_then(|key| key.strip_suffix('\''))
+        else {
+            return Ok(CipherKey::Passphrase(key.to_owned()));
+        };
+
+        // 32 bytes of key, optionally followed by 16 bytes of salt
+        if (hex.len() == 64 || hex.len() == 96) && hex.chars().all(|char| char.is_ascii_hexdigit())
+        {
+            Ok(CipherKey::Raw(hex.to_owned()))
+        } else {
+            Err(SQLError::Configuration(
+                "raw key must be x'<64 or 96 hex digits>'".into(),
+            ))
+        }
+    }
+
+    /// Value of `PRAGMA key`
+    fn pragma_value(&self) -> String {
+        match self {
+            CipherKey::Passphrase(passphrase) => quote_string(passphrase),
+            CipherKey::Raw(hex) => quote_string(&format!("x'{hex}'")),
+        }
+    }
+}
+
+/// Database opened read-only with connection options applied
+pub(crate) struct SqliteDatabase {
+    /// Archive path and member name for extracted databases
+    pub name: Option<String>,
+    pub connect_options: SqliteConnectOptions,
+    /// Copy of the database, removed when the last database is dropped
+    pub temp_dir: Option<Arc<TempDir>>,
+    /// Database is opened with SQLCipher key
+    pub encrypted: bool,
+    /// Database is opened as immutable, so its write-ahead log is ignored
+    pub immutable: bool,
+    /// File to load into in-memory database before the scan
+    pub load: Option<Load>,
+}
+
+/// File loaded into in-memory database
+pub(crate) enum Load {
+    /// SQL dump
+    Dump(PathBuf),
+    /// Data file loaded as a table
+    Data(PathBuf, DataFormat),
+}
+
+/// Build connection options from database URI and connection options.
+///
+/// Options sqlx doesn't know about are removed from the URI before it's parsed.
+/// Compressed and archived files are extracted, so there may be multiple databases.
+pub(crate) fn sqlite_databases(
+    database_uri: &str,
+    options: ConnectionOptions,
+) -> Result<Vec<SqliteDatabase>, SQLError> {
+    let (database_uri, uri_options) = split_uri_options(database_uri)?;
+    let options = options.merge(uri_options);
+
+    let connect_options = database_uri
+        .parse::<SqliteConnectOptions>()
+        .map_err(|error| SQLError::SqlX(("Database URI".into(), error)))?
+        .read_only(true);
+    let connect_options = apply_options(connect_options, &options)?;
+
+    let filename = connect_options.get_filename();
+    let from_dump = options.from_dump.unwrap_or_else(|| {
+        filename
+            .extension()
+            .is_some_and(|extension| extension == "sql")
+    });
+    let load = if from_dump {
+        Some(Load::Dump(filename.to_owned()))
+    } else {
+        options
+            .data_format
+            .or_else(|| DataFormat::from_path(filename))
+            .map(|format| Load::Data(filename.to_owned(), format))
+    };
+
+    if let Some(load) = load {
+        let connect_options = "sqlite::memory:"
+            .parse::<SqliteConnectOptions>()
+            .map_err(|error| SQLError::SqlX(("Database URI".into(), error)))?
+            .read_only(true);
+
+        return Ok(vec![SqliteDatabase {
+            name: None,
+            connect_options: apply_options(connect_options, &options)?,
+            temp_dir: None,
+            encrypted: false,
+            immutable: false,
+            load: Some(load),
+        }]);
+    }
+
+    let include_wal = options.include_wal.unwrap_or(false);
+    let immutable = options.immutable.unwrap_or(true) && !include_wal;
+    let encrypted = options.key.is_some();
+
+    if let Some(extracted) = extract_databases(connect_options.get_filename())? {
+        // Extracted files are copies already, write-ahead log next to them is applied
+        // only when they aren't opened as immutable
+        let temp_dir = Arc::new(extracted.dir);
+        return Ok(extracted
+            .databases
+            .into_iter()
+            .map(|(name, filename)| SqliteDatabase {
+                name: Some(name),
+                connect_options: connect_options
+                    .clone()
+                    .filename(filename)
+                    .immutable(immutable),
+                temp_dir: Some(temp_dir.clone()),
+                encrypted,
+                immutable,
+                load: None,
+            })
+            .collect());
+    }
+
+    let mut connect_options = connect_options.immutable(immutable);
+    let temp_dir = if include_wal {
+        let (snapshot, filename) = snapshot_database(connect_options.get_filename())?;
+        connect_options = connect_options.filename(filename);
+        Some(Arc::new(snapshot))
+    } else {
+        None
+    };
+
+    Ok(vec![SqliteDatabase {
+        name: None,
+        connect_options,
+        temp_dir,
+        encrypted,
+        immutable,
+        load: None,
+    }])
+}
+
+/// Writable connection to load a file into in-memory database
+pub(crate) async fn memory_connection(
+    connect_options: &SqliteConnectOptions,
+) -> Result<SqliteConnection, sqlx::Error> {
+    connect_options
+        .clone()
+        .read_only(false)
+        // Dumps insert rows table by table, not in order of foreign keys
+        .foreign_keys(false)
+        .connect()
+        .await
+}
+
+fn apply_options(
+    mut connect_options: SqliteConnectOptions,
+    options: &ConnectionOptions,
+) -> Result<SqliteConnectOptions, SQLError> {
+    if let Some(vfs) = &options.vfs {
+        connect_options = connect_options.vfs(vfs.clone());
+    }
+
+    // sqlx runs `key` and cipher PRAGMAs first, before anything reads the database
+    if let Some(key) = &options.key {
+        if !cfg!(feature = "sqlcipher") {
+            log::warn!("SQLCipher support isn't enabled, the key is ignored by SQLite");
+        }
+        connect_options = connect_options.pragma("key", key.pragma_value());
+    }
+
+    if let Some(version) = options.cipher_compatibility {
+        connect_options = connect_options.pragma("cipher_compatibility", version.to_string());
+    }
+
+    for (name, value) in &options.pragmas {
+        check_pragma_name(name)?;
+        check_pragma_value(name, value)?;
+        connect_options = connect_options.pragma(name.clone(), value.clone());
+    }
+
+    Ok(connect_options)
+}
+
+/// Split URI into URI known to sqlx and connection options
+fn split_uri_options(database_uri: &str) -> Result<(String, ConnectionOptions), SQLError> {
+    let mut options = ConnectionOptions::default();
+
+    let Some((database, query)) = database_uri.split_once('?') else {
+        return Ok((database_uri.to_owned(), options));
+    };
+
+    let mut params = vec![];
+    for param in query.split('&').filter(|param| !param.is_empty()) {
+        let (name, value) = param.split_once('=').unwrap_or((param, ""));
+        let value = || decode_uri_component(value);
+
+        match decode_uri_component(name)?.as_str() {
+            "immutable" => options.immutable = Some(parse_uri_bool("immutable", &value()?)?),
+            "include_wal" => options.include_wal = Some(parse_uri_bool("include_wal", &value()?)?),
+            "key" => options.key = Some(CipherKey::parse(&value()?)?),
+            "cipher_compatibility" => {
+                options.cipher_compatibility = Some(parse_cipher_compatibility(&value()?)?);
+            }
+            "vfs" => options.vfs = Some(value()?),
+            "from_dump" => options.from_dump = Some(parse_uri_bool("from_dump", &value()?)?),
+            "data_format" => options.data_format = Some(parse_data_format(&value()?)?),
+            name => match name.strip_prefix("pragma.") {
+                Some(pragma) => options.pragmas.push((pragma.to_owned(), value()?)),
+                None => params.push(param),
+            },
+        }
+    }
+
+    let database_uri = if params.is_empty() {
+        database.to_owned()
+    } else {
+        format!("{database}?{}", params.join("&"))
+    };
+
+    Ok((database_uri, options))
+}
+
+fn decode_uri_component(value: &str) -> Result<String, SQLError> {
+    percent_decode_str(&value.replace('+', " "))
+        .decode_utf8()
+        .map(|value| value.into_owned())
+        .map_err(|error| SQLError::Configuration(format!("Database URI: {error}")))
+}
+
+fn parse_uri_bool(name: &str, value: &str) -> Result<bool, SQLError> {
+    match value {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(SQLError::Configuration(format!(
+            "Database URI: unknown value {value:?} for `{name}`"
+        ))),
+    }
+}
+
+fn parse_data_format(value: &str) -> Result<DataFormat, SQLError> {
+    match value {
+        "csv" => Ok(DataFormat::Csv),
+        "tsv" => Ok(DataFormat::Tsv),
+        "ndjson" => Ok(DataFormat::Ndjson),
+        _ => Err(SQLError::Configuration(format!(
+            "Database URI: unknown value {value:?} for `data_format`"
+        ))),
+    }
+}
+
+/// SQLCipher major version, from 1 to 4
+fn parse_cipher_compatibility(value: &str) -> Result<u8, SQLError> {
+    match value.parse::<u8>() {
+        Ok(version @ 1..=4) => Ok(version),
+        _ => Err(SQLError::Configuration(format!(
+            "unknown SQLCipher version {value:?}, must be from 1 to 4"
+        ))),
+    }
+}
+
+fn check_pragma_name(name: &str) -> Result<(), SQLError> {
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|char| char.is_ascii_alphabetic() || char == '_')
+        && chars.all(|char| char.is_ascii_alphanumeric() || char == '_');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(SQLError::Configuration(format!(
+            "invalid PRAGMA name {name:?}"
+        )))
+    }
+}
+
+/// Value is inserted into SQL as is, so only a single literal is allowed:
+/// a number, a keyword like `off`, a quoted string or a BLOB literal
+fn check_pragma_value(name: &str, value: &str) -> Result<(), SQLError> {
+    let is_identifier = |value: &str| {
+        let mut chars = value.chars();
+        chars
+            .next()
+            .is_some_and(|char| char.is_ascii_alphabetic() || char == '_')
+            && chars.all(|char| char.is_ascii_alphanumeric() || char == '_')
+    };
+    let is_number = |value: &str| {
+        let digits = value.strip_prefix(['+', '-']).unwrap_or(value);
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, "0"));
+        !whole.is_empty()
+            && whole.chars().all(|char| char.is_ascii_digit())
+            && fraction.chars().all(|char| char.is_ascii_digit())
+    };
+    let is_string = |value: &str| {
+        value
+            .strip_prefix('\'')
+            .and_then(|value| value.strip_suffix('\''))
+            .is_some_and(|inner| !inner.replace("''", "").contains('\''))
+    };
+    let is_blob = |value: &str| {
+        value
+            .strip_prefix(['x', 'X'])
+            .and_then(|value| value.strip_prefix('\''))
+            .and_then(|value| value.strip_suffix('\''))
+            .is_some_and(|hex| {
+                hex.len() % 2 == 0 && hex.chars().all(|char| char.is_ascii_hexdigit())
+            })
+    };
+
+    if is_identifier(value) || is_number(value) || is_string(value) || is_blob(value) {
+        Ok(())
+    } else {
+        Err(SQLError::Configuration(format!(
+            "invalid value {value:?} of PRAGMA {name}, it must be a number, a keyword or a quoted string"
+        )))
+    }
+}
+
+#[inline]
+fn quote_string(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Copy database with its write-ahead log to a temporary directory.
+///
+/// SQLite applies the log to the copy, so the original files are never touched.
+fn snapshot_database(filename: &Path) -> Result<(TempDir, PathBuf), SQLError> {
+    let context = |action: &str| format!("{action} \"{}\"", filename.display());
+
+    let snapshot = tempfile::Builder::new()
+        .prefix("sqlgrep-")
+        .tempdir()
+        .map_err(|error| SQLError::Io((context("snapshot"), error)))?;
+
+    let name = filename.file_name().unwrap_or("database".as_ref());
+    let copy = snapshot.path().join(name);
+
+    std::fs::copy(filename, &copy).map_err(|error| SQLError::Io((context("copy"), error)))?;
+
+    let mut wal = filename.as_os_str().to_owned();
+    wal.push("-wal");
+    let mut wal_copy = copy.as_os_str().to_owned();
+    wal_copy.push("-wal");
+
+    match std::fs::copy(&wal, &wal_copy) {
+        Ok(_) => log::debug!("Write-ahead log is copied to {}", snapshot.path().display()),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            log::debug!("No write-ahead log for \"{}\"", filename.display());
+        }
+        Err(error) => return Err(SQLError::Io((context("copy write-ahead log of"), error))),
+    }
+
+    Ok((snapshot, copy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pragma_values_are_literals() {
+        for value in ["off", "WAL", "-2000", "1.5", "'a''b'", "x'0aFF'"] {
+            assert!(check_pragma_value("p", value).is_ok(), "{value}");
+        }
+        for value in ["", "1; DROP TABLE t", "'a' || 'b'", "'a'b'", "x'0'", "(1)"] {
+            assert!(check_pragma_value("p", value).is_err(), "{value}");
+        }
+    }
+}
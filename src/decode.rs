@@ -0,0 +1,334 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io::Read;
+
+use base64::alphabet::{STANDARD, URL_SAFE};
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::Engine as _;
+use percent_encoding::percent_decode;
+
+use crate::cell_to_string::{Cell, CellMatch};
+use crate::pattern::Pattern;
+
+/// Decompressed data is cut at this size
+const MAX_DECOMPRESSED: u64 = 16 * 1024 * 1024;
+
+/// Decoding applied to a cell value before it matches
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Decoding {
+    Base64,
+    Hex,
+    /// Percent-encoding
+    Url,
+    /// HTML entities
+    Html,
+    /// JSON string literal
+    Json,
+    Gzip,
+    Zlib,
+    Zstd,
+}
+
+impl Decoding {
+    const ALL: [Decoding; 8] = [
+        Decoding::Gzip,
+        Decoding::Zlib,
+        Decoding::Zstd,
+        Decoding::Json,
+        Decoding::Url,
+        Decoding::Html,
+        Decoding::Hex,
+        Decoding::Base64,
+    ];
+
+    /// Decoded value, `None` when the value isn't encoded this way
+    fn decode(self, value: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Decoding::Base64 => decode_base64(value),
+            Decoding::Hex => decode_hex(value),
+            Decoding::Url => {
+                let is_encoded = value.windows(3).any(|window| {
+                    window[0] == b'%' && window[1..].iter().all(u8::is_ascii_hexdigit)
+                });
+                is_encoded.then(|| percent_decode(value).collect())
+            }
+            Decoding::Html => decode_html(value),
+            Decoding::Json => {
+                let value = value.trim_ascii();
+                if value.len() < 2 || !value.starts_with(b"\"") || !value.ends_with(b"\"") {
+                    return None;
+                }
+                serde_json::from_slice::<String>(value)
+                    .ok()
+                    .map(String::into_bytes)
+            }
+            Decoding::Gzip if value.starts_with(&[0x1f, 0x8b]) => {
+                decompress(flate2::read::MultiGzDecoder::new(value))
+            }
+            Decoding::Zlib if is_zlib(value) => decompress(flate2::read::ZlibDecoder::new(value)),
+            Decoding::Zstd if value.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) => {
+                decompress(zstd::Decoder::new(value).ok()?)
+            }
+            Decoding::Gzip | Decoding::Zlib | Decoding::Zstd => None,
+        }
+    }
+}
+
+impl fmt::Display for Decoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Decoding::Base64 => "base64",
+            Decoding::Hex => "hex",
+            Decoding::Url => "url",
+            Decoding::Html => "html",
+            Decoding::Json => "json",
+            Decoding::Gzip => "gzip",
+            Decoding::Zlib => "zlib",
+            Decoding::Zstd => "zstd",
+        })
+    }
+}
+
+/// Decode the cell layer by layer up to the depth set in pattern options
+/// and return the first layer which matches with decodings applied to get it.
+///
+/// Text layers are matched as TEXT, other layers as BLOB.
+pub(crate) fn find_decoded(cell: &Cell<'_>, pattern: &Pattern) -> Option<CellMatch> {
+    let value: &[u8] = match cell {
+        Cell::Value(value) => value.as_bytes(),
+        Cell::Bytes(value) | Cell::Blob(value) => value,
+        Cell::Null | Cell::Unknown => return None,
+    };
+
+    let mut chain = vec![];
+    find_layer(value, pattern, pattern.options().decode_depth, &mut chain)
+}
+
+fn find_layer(
+    value: &[u8],
+    pattern: &Pattern,
+    depth: usize,
+    chain: &mut Vec<Decoding>,
+) -> Option<CellMatch> {
+    if depth == 0 {
+        return None;
+    }
+
+    for decoding in Decoding::ALL {
+        let Some(decoded) = decoding.decode(value) else {
+            continue;
+        };
+        if decoded.is_empty() || decoded == value {
+            continue;
+        }
+
+        chain.push(decoding);
+
+        let cell = match std::str::from_utf8(&decoded) {
+            Ok(text) => Cell::Value(Cow::Borrowed(text)),
+            Err(_) => Cell::Blob(Cow::Borrowed(&decoded)),
+        };
+        if let Some(mut found) = cell.find_layer(pattern) {
+            found.decoding = chain.clone();
+            return Some(found);
+        }
+        if let Some(found) = find_layer(&decoded, pattern, depth - 1, chain) {
+            return Some(found);
+        }
+
+        chain.pop();
+    }
+
+    None
+}
+
+fn decode_base64(value: &[u8]) -> Option<Vec<u8>> {
+    let value = value.trim_ascii();
+    // Short words are valid base64 too
+    if value.len() < 8 {
+        return None;
+    }
+
+    let config =
+        GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+    [STANDARD, URL_SAFE]
+        .iter()
+        .find_map(|alphabet| GeneralPurpose::new(alphabet, config).decode(value).ok())
+}
+
+fn decode_hex(value: &[u8]) -> Option<Vec<u8>> {
+    let value = value.trim_ascii();
+    if value.len() < 4 || !value.len().is_multiple_of(2) {
+        return None;
+    }
+
+    value
+        .chunks_exact(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).ok()?;
+            u8::from_str_radix(pair, 16).ok()
+        })
+        .collect()
+}
+
+/// Decode named entities used by HTML escaping and numeric character references
+fn decode_html(value: &[u8]) -> Option<Vec<u8>> {
+    let text = std::str::from_utf8(value).ok()?;
+    if !text.contains('&') {
+        return None;
+    }
+
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut decoded = false;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest[1..]
+            .find(';')
+            .filter(|end| *end <= 10)
+            .and_then(|end| Some((html_entity(&rest[1..=end])?, end + 2)));
+        match entity {
+            Some((c, length)) => {
+                result.push(c);
+                rest = &rest[length..];
+                decoded = true;
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+
+    decoded.then(|| result.into_bytes())
+}
+
+/// Character of an entity between `&` and `;`
+fn html_entity(entity: &str) -> Option<char> {
+    let code = if let Some(hex) = entity
+        .strip_prefix("#x")
+        .or_else(|| entity.strip_prefix("#X"))
+    {
+        u32::from_str_radix(hex, 16).ok()?
+    } else if let Some(decimal) = entity.strip_prefix('#') {
+        decimal.parse().ok()?
+    } else {
+        return match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => None,
+        };
+    };
+
+    char::from_u32(code)
+}
+
+/// zlib header: deflate method and checksum of the first two bytes
+fn is_zlib(value: &[u8]) -> bool {
+    match value {
+        [cmf, flg, ..] => cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0,
+        _ => false,
+    }
+}
+
+fn decompress(reader: impl Read) -> Option<Vec<u8>> {
+    let mut result = vec![];
+    reader
+        .take(MAX_DECOMPRESSED)
+        .read_to_end(&mut result)
+        .ok()?;
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write as _;
+
+    use base64::engine::general_purpose::STANDARD as BASE64;
+
+    use super::*;
+    use crate::pattern::{PatternKind, PatternOptions};
+
+    fn pattern(decode_depth: usize) -> Pattern {
+        let options = PatternOptions {
+            decode_depth,
+            ..PatternOptions::default()
+        };
+        Pattern::new("needle", &PatternKind::Fixed, options).unwrap()
+    }
+
+    fn decodings(value: &[u8], depth: usize) -> Option<Vec<Decoding>> {
+        let cell = match std::str::from_utf8(value) {
+            Ok(text) => Cell::Value(Cow::Borrowed(text)),
+            Err(_) => Cell::Blob(Cow::Borrowed(value)),
+        };
+        find_decoded(&cell, &pattern(depth)).map(|found| found.decoding)
+    }
+
+    fn gzip(value: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(value).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zlib(value: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(value).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn single_layers_are_decoded() {
+        let cases = [
+            (
+                BASE64.encode("a needle here").into_bytes(),
+                Decoding::Base64,
+            ),
+            (b"61206e6565646c65".to_vec(), Decoding::Hex),
+            (b"a%20needle".to_vec(), Decoding::Url),
+            (b"&lt;needle&#x3e;".to_vec(), Decoding::Html),
+            (br#""a needle""#.to_vec(), Decoding::Json),
+            (gzip(b"a needle"), Decoding::Gzip),
+            (zlib(b"a needle"), Decoding::Zlib),
+            (
+                zstd::encode_all(&b"a needle"[..], 0).unwrap(),
+                Decoding::Zstd,
+            ),
+        ];
+
+        for (value, decoding) in cases {
+            assert_eq!(decodings(&value, 1), Some(vec![decoding]), "{decoding}");
+        }
+    }
+
+    #[test]
+    fn nested_layers_are_decoded_up_to_depth() {
+        let value = BASE64.encode(gzip(b"{\"key\": \"needle\"}")).into_bytes();
+
+        assert_eq!(decodings(&value, 1), None);
+        assert_eq!(
+            decodings(&value, 2),
+            Some(vec![Decoding::Base64, Decoding::Gzip])
+        );
+        assert_eq!(decodings(&value, 0), None);
+    }
+
+    #[test]
+    fn values_which_arent_encoded_are_skipped() {
+        assert_eq!(Decoding::Base64.decode(b"needle"), None);
+        assert_eq!(Decoding::Hex.decode(b"6e6"), None);
+        assert_eq!(Decoding::Url.decode(b"100%"), None);
+        assert_eq!(Decoding::Html.decode(b"a & b; c"), None);
+        assert_eq!(Decoding::Json.decode(b"\""), None);
+        assert_eq!(Decoding::Zlib.decode(b"needle"), None);
+    }
+}
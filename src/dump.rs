@@ -0,0 +1,183 @@
+use std::path::Path;
+
+use sqlparser::ast::Statement;
+use sqlparser::dialect::{Dialect, SQLiteDialect};
+use sqlparser::keywords::Keyword;
+use sqlparser::parser::Parser;
+use sqlparser::tokenizer::{Location, Token};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection};
+use sqlx::Executor as _;
+
+use crate::connection::memory_connection;
+use crate::error::SQLError;
+
+/// Failed statements reported one by one, the rest are only counted
+const MAX_REPORTED_FAILURES: usize = 10;
+
+/// Replay SQL dump into a new connection to an in-memory database.
+///
+/// Database lives while the connection is open.
+/// Statements which can't be parsed, aren't supported or fail are skipped with a warning.
+pub(crate) async fn load_dump(
+    path: &Path,
+    connect_options: &SqliteConnectOptions,
+) -> Result<SqliteConnection, SQLError> {
+    let context = format!("load dump \"{}\"", path.display());
+
+    let file = path.to_owned();
+    let sql = tokio::task::spawn_blocking(move || std::fs::read_to_string(file))
+        .await
+        .map_err(std::io::Error::other)
+        .and_then(|result| result)
+        .map_err(|error| SQLError::Io((context.clone(), error)))?;
+    let statements = split_dump(&sql, &SQLiteDialect {})?;
+
+    let mut connection = memory_connection(connect_options)
+        .await
+        .map_err(|error| SQLError::SqlX((context.clone(), error)))?;
+
+    let (mut loaded, mut failed) = (0, 0);
+    for (line, statement) in statements {
+        match connection.execute(statement).await {
+            Ok(_) => loaded += 1,
+            Err(error) => {
+                failed += 1;
+                if failed <= MAX_REPORTED_FAILURES {
+                    log::warn!(
+                        "Skip statement at line {line} of {}: {error}",
+                        path.display()
+                    );
+                }
+            }
+        }
+    }
+
+    if failed > MAX_REPORTED_FAILURES {
+        log::warn!(
+            "Skip {} more failed statements of {}",
+            failed - MAX_REPORTED_FAILURES,
+            path.display()
+        );
+    }
+    log::debug!("Loaded {loaded} statements of {}", path.display());
+
+    Ok(connection)
+}
+
+/// Split dump into statements with their line numbers.
+///
+/// Only statements which create and fill tables are kept,
+/// so a dump can't touch anything outside of the in-memory database, e.g. with `ATTACH`.
+fn split_dump<'a>(sql: &'a str, dialect: &dyn Dialect) -> Result<Vec<(u64, &'a str)>, SQLError> {
+    let lines = LineOffsets::new(sql);
+    let mut parser = Parser::new(dialect)
+        .try_with_sql(sql)
+        .map_err(SQLError::ParseError)?;
+
+    let mut statements = vec![];
+    loop {
+        while parser.consume_token(&Token::SemiColon) {}
+
+        let start = parser.peek_token();
+        if start.token == Token::EOF {
+            break;
+        }
+        let line = start.span.start.line;
+
+        match parser.parse_statement() {
+            Ok(statement) => {
+                let end = parser.peek_token();
+                let end_offset = match end.token {
+                    Token::EOF => sql.len(),
+                    Token::SemiColon => lines.offset(end.span.start),
+                    _ => {
+                        log::warn!("Skip statement at line {line}: unexpected {}", end.token);
+                        skip_statement(&mut parser);
+                        continue;
+                    }
+                };
+
+                if is_supported(&statement) {
+                    statements.push((line, &sql[lines.offset(start.span.start)..end_offset]));
+                } else {
+                    log::warn!("Skip unsupported {} statement at line {line}", start.token);
+                }
+            }
+            // Dumps made by sqlite3 start with `PRAGMA foreign_keys=OFF`, which isn't parsed
+            Err(error) if matches!(&start.token, Token::Word(word) if word.keyword == Keyword::PRAGMA) =>
+            {
+                log::debug!("Skip PRAGMA at line {line}: {error}");
+                skip_statement(&mut parser);
+            }
+            Err(error) => {
+                log::warn!("Skip statement at line {line}: {error}");
+                skip_statement(&mut parser);
+            }
+        }
+    }
+
+    Ok(statements)
+}
+
+/// Statements which only create or change schema and data
+fn is_supported(statement: &Statement) -> bool {
+    matches!(
+        statement,
+        Statement::CreateTable { .. }
+            | Statement::CreateVirtualTable { .. }
+            | Statement::CreateIndex { .. }
+            | Statement::CreateView { .. }
+            | Statement::CreateTrigger { .. }
+            | Statement::AlterTable { .. }
+            | Statement::Drop { .. }
+            | Statement::Insert { .. }
+            | Statement::Update { .. }
+            | Statement::Delete { .. }
+            | Statement::StartTransaction { .. }
+            | Statement::Commit { .. }
+            | Statement::Rollback { .. }
+            | Statement::Pragma { .. }
+    )
+}
+
+/// Skip tokens up to the end of the current statement
+fn skip_statement(parser: &mut Parser<'_>) {
+    loop {
+        match parser.next_token().token {
+            Token::SemiColon | Token::EOF => break,
+            _ => {}
+        }
+    }
+}
+
+/// Convert token locations to byte offsets
+struct LineOffsets<'a> {
+    sql: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> LineOffsets<'a> {
+    fn new(sql: &'a str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(sql.match_indices('\n').map(|(offset, _)| offset + 1))
+            .collect();
+
+        Self { sql, starts }
+    }
+
+    /// Lines and columns start from 1, columns are counted in characters
+    fn offset(&self, location: Location) -> usize {
+        let Some(&start) = usize::try_from(location.line)
+            .ok()
+            .and_then(|line| self.starts.get(line.saturating_sub(1)))
+        else {
+            return self.sql.len();
+        };
+
+        let column = usize::try_from(location.column.saturating_sub(1)).unwrap_or(usize::MAX);
+        self.sql[start..]
+            .char_indices()
+            .nth(column)
+            .map_or(self.sql.len(), |(offset, _)| start + offset)
+    }
+}
@@ -0,0 +1,327 @@
+use std::fmt;
+use std::ops::Range;
+
+use aho_corasick::AhoCorasick;
+use base64::alphabet::{Alphabet, STANDARD, URL_SAFE};
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine as _;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use crate::error::SQLError;
+
+/// Encoding a value is stored in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Encoding {
+    /// As is
+    Plain,
+    /// Base64 with standard or URL-safe alphabet
+    Base64,
+    /// Hex digits in any case
+    Hex,
+    /// Percent-encoded
+    Url,
+    /// UTF-16 little endian
+    Utf16Le,
+    /// UTF-16 big endian
+    Utf16Be,
+}
+
+impl Encoding {
+    /// Encodings a pattern is searched in, except [`Encoding::Plain`] which is always searched
+    pub const ALL: [Encoding; 5] = [
+        Encoding::Base64,
+        Encoding::Hex,
+        Encoding::Url,
+        Encoding::Utf16Le,
+        Encoding::Utf16Be,
+    ];
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Encoding::Plain => "plain",
+            Encoding::Base64 => "base64",
+            Encoding::Hex => "hex",
+            Encoding::Url => "url",
+            Encoding::Utf16Le => "utf16le",
+            Encoding::Utf16Be => "utf16be",
+        })
+    }
+}
+
+/// Characters which are kept as is by URL encoding
+const URL_UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Fixed string and its encoded forms matched at once
+pub struct EncodedPattern {
+    needles: Vec<Needle>,
+    /// Needles which are compared ignoring ASCII case
+    caseless: Option<AhoCorasick>,
+    /// Needles which are compared as is
+    exact: Option<AhoCorasick>,
+}
+
+struct Needle {
+    bytes: Vec<u8>,
+    encoding: Encoding,
+    caseless: bool,
+}
+
+impl EncodedPattern {
+    /// Encoded forms of the pattern.
+    ///
+    /// Hex and URL encoding ignore case of hex digits. Plain and UTF-16 forms ignore case
+    /// when `case_insensitive` is set. Base64 is searched at every alignment to find it
+    /// inside a longer encoded value, unless `whole_string` is set.
+    pub(crate) fn new(
+        pattern: &str,
+        encodings: &[Encoding],
+        case_insensitive: bool,
+        whole_string: bool,
+    ) -> Result<Self, SQLError> {
+        let mut needles = vec![];
+        let mut push = |bytes: Vec<u8>, encoding, caseless| {
+            let is_new = !needles
+                .iter()
+                .any(|needle: &Needle| needle.bytes == bytes && needle.caseless == caseless);
+            if !bytes.is_empty() && is_new {
+                needles.push(Needle {
+                    bytes,
+                    encoding,
+                    caseless,
+                });
+            }
+        };
+
+        push(
+            pattern.as_bytes().to_vec(),
+            Encoding::Plain,
+            case_insensitive,
+        );
+        for encoding in encodings {
+            match encoding {
+                Encoding::Plain => {}
+                Encoding::Base64 => {
+                    for bytes in base64_forms(pattern.as_bytes(), whole_string) {
+                        push(bytes, Encoding::Base64, false);
+                    }
+                }
+                Encoding::Hex => {
+                    let hex = pattern
+                        .bytes()
+                        .map(|byte| format!("{byte:02x}"))
+                        .collect::<String>();
+                    push(hex.into_bytes(), Encoding::Hex, true);
+                }
+                Encoding::Url => {
+                    let url = utf8_percent_encode(pattern, URL_UNRESERVED).to_string();
+                    if url != pattern {
+                        push(url.into_bytes(), Encoding::Url, true);
+                    }
+                }
+                Encoding::Utf16Le => {
+                    let bytes = pattern.encode_utf16().flat_map(u16::to_le_bytes).collect();
+                    push(bytes, Encoding::Utf16Le, case_insensitive);
+                }
+                Encoding::Utf16Be => {
+                    let bytes = pattern.encode_utf16().flat_map(u16::to_be_bytes).collect();
+                    push(bytes, Encoding::Utf16Be, case_insensitive);
+                }
+            }
+        }
+
+        let build = |caseless: bool| {
+            let patterns = needles
+                .iter()
+                .filter(|needle| needle.caseless == caseless)
+                .map(|needle| &needle.bytes)
+                .collect::<Vec<_>>();
+            if patterns.is_empty() {
+                return Ok(None);
+            }
+
+            AhoCorasick::builder()
+                .ascii_case_insensitive(caseless)
+                .build(patterns)
+                .map(Some)
+                .map_err(|error| SQLError::Configuration(format!("pattern: {error}")))
+        };
+        let caseless = build(true)?;
+        let exact = build(false)?;
+
+        Ok(Self {
+            needles,
+            caseless,
+            exact,
+        })
+    }
+
+    /// Range and encoding of the first match
+    pub(crate) fn find(
+        &self,
+        value: &[u8],
+        whole_string: bool,
+    ) -> Option<(Range<usize>, Encoding)> {
+        if whole_string {
+            return self
+                .needles
+                .iter()
+                .find(|needle| {
+                    if needle.caseless {
+                        value.eq_ignore_ascii_case(&needle.bytes)
+                    } else {
+                        value == needle.bytes
+                    }
+                })
+                .map(|needle| (0..value.len(), needle.encoding));
+        }
+
+        let find = |caseless: bool, searcher: &Option<AhoCorasick>| {
+            let found = searcher.as_ref()?.find(value)?;
+            let needle = self
+                .needles
+                .iter()
+                .filter(|needle| needle.caseless == caseless)
+                .nth(found.pattern().as_usize())?;
+            Some((found.range(), needle.encoding))
+        };
+
+        match (find(true, &self.caseless), find(false, &self.exact)) {
+            (Some(caseless), Some(exact)) => Some(if exact.0.start < caseless.0.start {
+                exact
+            } else {
+                caseless
+            }),
+            (caseless, exact) => caseless.or(exact),
+        }
+    }
+}
+
+/// Base64 forms of bytes with standard and URL-safe alphabet.
+///
+/// Bytes inside a longer value are encoded differently depending on their offset,
+/// so one form is made for every offset modulo 3 without characters which depend
+/// on the bytes around.
+fn base64_forms(bytes: &[u8], whole_string: bool) -> Vec<Vec<u8>> {
+    let mut forms = vec![];
+    for alphabet in [STANDARD, URL_SAFE] {
+        if whole_string {
+            forms.push(base64_engine(&alphabet, true).encode(bytes).into_bytes());
+            continue;
+        }
+
+        let engine = base64_engine(&alphabet, false);
+        for shift in 0..3 {
+            let mut shifted = vec![0; shift];
+            shifted.extend_from_slice(bytes);
+            let encoded = engine.encode(&shifted).into_bytes();
+
+            // The first characters are mixed with bytes before the value
+            let start = [0, 2, 3][shift];
+            // The last character is mixed with bytes after the value
+            let end = if shifted.len() % 3 == 0 {
+                encoded.len()
+            } else {
+                encoded.len() - 1
+            };
+            if start < end {
+                forms.push(encoded[start..end].to_vec());
+            }
+        }
+    }
+    forms
+}
+
+fn base64_engine(alphabet: &Alphabet, padding: bool) -> GeneralPurpose {
+    GeneralPurpose::new(
+        alphabet,
+        GeneralPurposeConfig::new().with_encode_padding(padding),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &EncodedPattern, value: &[u8]) -> Option<Encoding> {
+        pattern.find(value, false).map(|(_, encoding)| encoding)
+    }
+
+    #[test]
+    fn base64_is_found_at_every_offset() {
+        let pattern = EncodedPattern::new("secret", &[Encoding::Base64], false, false).unwrap();
+
+        for prefix in ["", "a", "ab", "abc"] {
+            for suffix in ["", "x", "xy"] {
+                let value = format!("{prefix}secret{suffix}");
+                for encoded in [
+                    base64::engine::general_purpose::STANDARD.encode(&value),
+                    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(&value),
+                ] {
+                    assert_eq!(
+                        find(&pattern, encoded.as_bytes()),
+                        Some(Encoding::Base64),
+                        "{encoded}"
+                    );
+                }
+            }
+        }
+        assert_eq!(find(&pattern, b"c2VjcmV"), None);
+    }
+
+    #[test]
+    fn whole_base64_value_is_padded() {
+        let pattern = EncodedPattern::new("secret!", &[Encoding::Base64], false, true).unwrap();
+
+        assert!(pattern.find(b"c2VjcmV0IQ==", true).is_some());
+        assert!(pattern.find(b"c2VjcmV0IQ", true).is_none());
+    }
+
+    #[test]
+    fn hex_and_url_ignore_case_of_digits() {
+        let encodings = [Encoding::Hex, Encoding::Url];
+        let pattern = EncodedPattern::new("a b/é", &encodings, false, false).unwrap();
+
+        assert_eq!(find(&pattern, b"x612062_2fC3A9"), None);
+        assert_eq!(find(&pattern, b"x6120622FC3A9"), Some(Encoding::Hex));
+        assert_eq!(find(&pattern, b"?q=a%20b%2f%C3%a9"), Some(Encoding::Url));
+        assert_eq!(find(&pattern, b"A B/\xc3\xa9"), None);
+        assert_eq!(find(&pattern, "a b/é".as_bytes()), Some(Encoding::Plain));
+    }
+
+    #[test]
+    fn utf16_follows_case_option() {
+        let encodings = [Encoding::Utf16Le, Encoding::Utf16Be];
+        let exact = EncodedPattern::new("Key", &encodings, false, false).unwrap();
+        let caseless = EncodedPattern::new("Key", &encodings, true, false).unwrap();
+
+        assert_eq!(
+            find(&exact, b"\x00K\x00e\x00y\x00"),
+            Some(Encoding::Utf16Be)
+        );
+        assert_eq!(find(&exact, b"K\x00e\x00y\x00"), Some(Encoding::Utf16Le));
+        assert_eq!(find(&exact, b"k\x00E\x00Y\x00"), None);
+        assert_eq!(find(&caseless, b"k\x00E\x00Y\x00"), Some(Encoding::Utf16Le));
+    }
+
+    #[test]
+    fn the_first_match_is_reported() {
+        let encodings = [Encoding::Hex];
+        let pattern = EncodedPattern::new("ab", &encodings, false, false).unwrap();
+
+        assert_eq!(
+            pattern.find(b"--6162--ab", false),
+            Some((2..6, Encoding::Hex))
+        );
+        assert_eq!(
+            pattern.find(b"--ab--6162", false),
+            Some((2..4, Encoding::Plain))
+        );
+    }
+}
@@ -1,67 +1,83 @@
+use std::fmt;
+
 use log::Level as LogLevel;
 use sqlparser::parser::ParserError;
 
-pub(crate) type Level = LogLevel;
+pub type Level = LogLevel;
 
-pub(crate) enum SQLError {
+#[derive(Debug)]
+pub enum SQLError {
     Regex(regex::Error),
     QueryError(QueryError),
     ParseError(ParserError),
     SqlX((String, sqlx::Error)),
     ConvertCell((String, String)),
     Io((String, std::io::Error)),
+    Timeout(String),
+    SourcesFailed(usize),
+    Configuration(String),
 }
 
-pub(crate) enum QueryError {
+#[derive(Debug)]
+pub enum QueryError {
     ReadOnlyQueryAllowed,
 }
 
 impl SQLError {
     /// Report and return error code if needed
     pub fn report(&self, level: Level) -> i32 {
-        match self {
-            SQLError::Regex(error) => {
-                log::log!(level, "Regex error: {error}");
+        log::log!(level, "{self}");
 
-                64
-            }
-            SQLError::QueryError(query_error) => {
-                match query_error {
-                    QueryError::ReadOnlyQueryAllowed => {
-                        log::log!(level, "Only readonly query is allowed");
-                    }
-                }
+        self.exit_code()
+    }
 
-                65
-            }
-            SQLError::ParseError(error) => {
-                log::log!(level, "Unable to parse SQL: {error}");
+    /// Process exit code for the error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            SQLError::Regex(_) | SQLError::Configuration(_) => 64,
+            SQLError::QueryError(_) => 65,
+            SQLError::ParseError(_) => 66,
+            SQLError::Io(_) => 70,
+            SQLError::SourcesFailed(_) => 1,
+            SQLError::SqlX(_) => 74,
+            SQLError::ConvertCell(_) => 73,
+            SQLError::Timeout(_) => 75,
+        }
+    }
+}
 
-                66
-            }
+impl fmt::Display for SQLError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SQLError::Regex(error) => write!(f, "Regex error: {error}"),
+            SQLError::QueryError(query_error) => match query_error {
+                QueryError::ReadOnlyQueryAllowed => write!(f, "Only readonly query is allowed"),
+            },
+            SQLError::ParseError(error) => write!(f, "Unable to parse SQL: {error}"),
             SQLError::Io((context, error)) => {
                 let context = format_context(context);
-                log::log!(level, "IO error{context}: {error}");
-
-                70
+                write!(f, "IO error{context}: {error}")
             }
             SQLError::SqlX((context, error)) => {
                 let context = format_context(context);
-                log::log!(level, "SQL error{context}: {error}");
-
-                74
+                write!(f, "SQL error{context}: {error}")
             }
             SQLError::ConvertCell((context, error)) => {
                 let context = format_context(context);
-
-                log::log!(level, "Cell conversion error{context}: {error}");
-
-                73
+                write!(f, "Cell conversion error{context}: {error}")
+            }
+            SQLError::Timeout(context) => {
+                let context = format_context(context);
+                write!(f, "Timeout{context}")
             }
+            SQLError::SourcesFailed(count) => write!(f, "{count} table(s) or query(ies) failed"),
+            SQLError::Configuration(error) => write!(f, "Configuration error: {error}"),
         }
     }
 }
 
+impl std::error::Error for SQLError {}
+
 #[inline]
 fn format_context(context: &String) -> String {
     if context.is_empty() {
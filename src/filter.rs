@@ -0,0 +1,219 @@
+use sqlparser::ast::{BinaryOperator, Expr, Value};
+use sqlparser::dialect::Dialect;
+use sqlparser::parser::Parser;
+
+use crate::error::SQLError;
+use crate::pattern::Pattern;
+use crate::select::escape_table_name;
+
+///
+/// Generates `WHERE` expression to let database discard rows which can't match the pattern.
+///
+/// Expression matches a superset of rows [`Pattern::is_match`] accepts, so the pattern
+/// still must be checked for every cell. `None` is returned when every row has to be read.
+///
+/// ```sql
+/// (typeof("name") = 'real' OR instr("name", 'value') > 0) OR ...
+/// ```
+///
+/// Real values are always passed through as SQLite and Rust format them differently,
+/// so are BLOB values when they are matched. Binary patterns pass BLOB values only.
+/// Regular expressions and caseless patterns aren't checked in SQL, `REGEXP` of sqlx
+/// fails on text which isn't valid UTF-8.
+///
+pub(crate) fn generate_filter(
+    pattern: &Pattern,
+    columns: &[String],
+    dialect: &impl Dialect,
+) -> Result<Option<Expr>, SQLError> {
+    // Decoded values, escaped JSON strings and protobuf fields can't be checked in SQL
+    let options = pattern.options();
+    if options.decode_depth > 0 || options.json_cells || options.protobuf {
+        return Ok(None);
+    }
+
+    let Some(column_filter) = ColumnFilter::new(pattern) else {
+        return Ok(None);
+    };
+
+    if columns.is_empty() {
+        return Ok(None);
+    }
+
+    // BLOB isn't converted to text, so it's matched by `sqlgrep` only
+    let passed = match pattern {
+        Pattern::Binary(_) => None,
+        _ if pattern.options().blobs => Some("IN ('real', 'blob')"),
+        _ => Some("= 'real'"),
+    };
+
+    let filter = columns
+        .iter()
+        .map(|column| {
+            let column = escape_table_name(column, dialect);
+            let column_filter = column_filter.render(&column);
+            match passed {
+                Some(passed) => format!("(typeof({column}) {passed} OR {column_filter})"),
+                None => format!("({column_filter})"),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" OR ");
+
+    log::trace!("Generated filter: {filter}");
+
+    Parser::new(dialect)
+        .try_with_sql(&filter)
+        .and_then(|mut parser| parser.parse_expr())
+        .map(Some)
+        .map_err(SQLError::ParseError)
+}
+
+///
+/// Generates `WHERE` expression to select roughly `percent` of table rows evenly by `rowid`.
+///
+/// ```sql
+/// abs(rowid) % 10000 < 150
+/// ```
+///
+pub(crate) fn generate_sample(percent: f64, dialect: &impl Dialect) -> Result<Expr, SQLError> {
+    let threshold = (percent * 100.0).round();
+    let filter = format!("abs(rowid) % 10000 < {threshold}");
+
+    log::trace!("Generated sample: {filter}");
+
+    Parser::new(dialect)
+        .try_with_sql(&filter)
+        .and_then(|mut parser| parser.parse_expr())
+        .map_err(SQLError::ParseError)
+}
+
+/// Combine filters so every one of them should match
+pub(crate) fn combine_filters(filters: Vec<Expr>) -> Option<Expr> {
+    filters
+        .into_iter()
+        .map(|filter| Expr::Nested(Box::new(filter)))
+        .reduce(|left, right| Expr::BinaryOp {
+            left: Box::new(left),
+            op: BinaryOperator::And,
+            right: Box::new(right),
+        })
+}
+
+/// Filter for a single column
+enum ColumnFilter {
+    /// Quoted fixed string
+    Contains(String),
+    /// Hex literal to find in BLOB
+    ContainsBytes(String),
+    /// Any BLOB
+    Blob,
+}
+
+impl ColumnFilter {
+    fn new(pattern: &Pattern) -> Option<Self> {
+        match pattern {
+            // Encoded forms may be in any case and in BLOB
+            Pattern::Always(_) | Pattern::Encoded(_) | Pattern::Regex(_) => None,
+            // SQLite `lower` and `LIKE` are ASCII only
+            Pattern::Fixed((_, options)) if options.case_insensitive => None,
+            Pattern::Fixed((value, _)) => Some(Self::Contains(quote(value.as_str()))),
+            Pattern::Binary((binary, _)) => Some(match binary.hex_bytes() {
+                Some(bytes) => Self::ContainsBytes(hex_literal(bytes)),
+                None => Self::Blob,
+            }),
+        }
+    }
+
+    /// Render filter for escaped column name
+    fn render(&self, column: &str) -> String {
+        match self {
+            ColumnFilter::Contains(value) => format!("instr({column}, {value}) > 0"),
+            ColumnFilter::ContainsBytes(value) => {
+                format!("typeof({column}) = 'blob' AND instr({column}, {value}) > 0")
+            }
+            ColumnFilter::Blob => format!("typeof({column}) = 'blob'"),
+        }
+    }
+}
+
+#[inline]
+fn quote(value: &str) -> String {
+    Value::SingleQuotedString(value.to_owned()).to_string()
+}
+
+#[inline]
+fn hex_literal(bytes: &[u8]) -> String {
+    let hex = bytes
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<String>();
+    Value::HexStringLiteral(hex).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlparser::dialect::SQLiteDialect;
+
+    use super::*;
+    use crate::pattern::{PatternKind, PatternOptions};
+
+    fn filter(pattern: &str, kind: PatternKind, options: PatternOptions) -> Option<String> {
+        let pattern = Pattern::new(pattern, &kind, options).unwrap();
+        let columns = ["a".to_owned(), "b c".to_owned()];
+        generate_filter(&pattern, &columns, &SQLiteDialect {})
+            .unwrap()
+            .map(|filter| filter.to_string())
+    }
+
+    #[test]
+    fn fixed_string_is_checked_with_instr() {
+        assert_eq!(
+            filter("it's", PatternKind::Fixed, PatternOptions::default()).as_deref(),
+            Some(
+                "(typeof(`a`) = 'real' OR instr(`a`, 'it''s') > 0) \
+                 OR (typeof(`b c`) = 'real' OR instr(`b c`, 'it''s') > 0)"
+            )
+        );
+    }
+
+    #[test]
+    fn blobs_are_passed_through() {
+        let options = PatternOptions {
+            blobs: true,
+            ..PatternOptions::default()
+        };
+        let filter = filter("x", PatternKind::Fixed, options).unwrap();
+        assert!(filter.starts_with("(typeof(`a`) IN ('real', 'blob') OR instr(`a`, 'x') > 0)"));
+    }
+
+    #[test]
+    fn hex_pattern_matches_blobs_only() {
+        let filter = filter("DEADbeef", PatternKind::Hex, PatternOptions::default()).unwrap();
+        assert!(filter.starts_with("(typeof(`a`) = 'blob' AND instr(`a`, X'DEADBEEF') > 0)"));
+    }
+
+    #[test]
+    fn regex_and_caseless_patterns_are_not_filtered() {
+        assert_eq!(
+            filter("ne+dle", PatternKind::Regex, PatternOptions::default()),
+            None
+        );
+        let options = PatternOptions {
+            case_insensitive: true,
+            ..PatternOptions::default()
+        };
+        assert_eq!(filter("needle", PatternKind::Fixed, options), None);
+        let options = PatternOptions {
+            decode_depth: 1,
+            ..PatternOptions::default()
+        };
+        assert_eq!(filter("needle", PatternKind::Fixed, options), None);
+    }
+
+    #[test]
+    fn sample_takes_percent_of_rowids() {
+        let sample = generate_sample(1.5, &SQLiteDialect {}).unwrap();
+        assert_eq!(sample.to_string(), "abs(rowid) % 10000 < 150");
+    }
+}
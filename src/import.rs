@@ -0,0 +1,332 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead as _, BufReader};
+use std::path::Path;
+
+use serde_json::{Map, Value};
+use sqlparser::dialect::SQLiteDialect;
+use sqlx::sqlite::{SqliteArguments, SqliteConnectOptions, SqliteConnection};
+use sqlx::{Arguments as _, Connection as _, Executor as _};
+
+use crate::connection::memory_connection;
+use crate::error::SQLError;
+use crate::select::escape_table_name;
+
+/// Format of a data file loaded as a table
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataFormat {
+    /// Comma separated values with a header row
+    Csv,
+    /// Tab separated values with a header row
+    Tsv,
+    /// JSON object on every line
+    Ndjson,
+}
+
+impl DataFormat {
+    /// Detect format by file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+
+        match extension.as_str() {
+            "csv" => Some(DataFormat::Csv),
+            "tsv" | "tab" => Some(DataFormat::Tsv),
+            "ndjson" | "jsonl" => Some(DataFormat::Ndjson),
+            _ => None,
+        }
+    }
+}
+
+/// Load data file into a table of a new in-memory database.
+///
+/// Table is named after the file without extension, e.g. `history` for `history.csv`.
+/// Rows are inserted as they are read. Database lives while the connection is open.
+pub(crate) async fn load_data_file(
+    path: &Path,
+    format: DataFormat,
+    connect_options: &SqliteConnectOptions,
+) -> Result<SqliteConnection, SQLError> {
+    let context = format!("load \"{}\"", path.display());
+    let sql_error = |error| SQLError::SqlX((context.clone(), error));
+
+    let table = path
+        .file_stem()
+        .map_or_else(|| "data".into(), |stem| stem.to_string_lossy());
+
+    let mut connection = memory_connection(connect_options)
+        .await
+        .map_err(sql_error)?;
+
+    let mut transaction = connection.begin().await.map_err(sql_error)?;
+    let rows = match format {
+        DataFormat::Csv => load_delimited(&mut transaction, &table, b',', path).await?,
+        DataFormat::Tsv => load_delimited(&mut transaction, &table, b'\t', path).await?,
+        DataFormat::Ndjson => load_ndjson(&mut transaction, &table, path).await?,
+    };
+    transaction.commit().await.map_err(sql_error)?;
+
+    log::debug!("Loaded {rows} rows of {}", path.display());
+
+    Ok(connection)
+}
+
+/// Value of a loaded cell
+enum Field {
+    Json(Value),
+    /// Text which isn't valid UTF-8, kept as it is
+    Bytes(Vec<u8>),
+}
+
+/// Table rows are inserted into
+struct Loader<'p> {
+    path: &'p Path,
+    insert: String,
+    columns: usize,
+    rows: u64,
+}
+
+impl<'p> Loader<'p> {
+    /// Create table with columns of the declared type, empty one for no type affinity
+    async fn create(
+        connection: &mut SqliteConnection,
+        table: &str,
+        columns: &[String],
+        declared: &str,
+        path: &'p Path,
+    ) -> Result<Self, SQLError> {
+        if columns.is_empty() {
+            return Err(SQLError::Configuration(format!(
+                "no columns found in {}",
+                path.display()
+            )));
+        }
+
+        let dialect = SQLiteDialect {};
+        let table = escape_table_name(table, &dialect);
+        let names = columns
+            .iter()
+            .map(|column| {
+                let column = escape_table_name(column, &dialect);
+                format!("{column} {declared}").trim_end().to_owned()
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        // Bytes are bound as BLOB and converted to the declared type, so invalid text stays TEXT
+        let placeholder = match declared {
+            "" => "?".to_owned(),
+            declared => format!("CAST(? AS {declared})"),
+        };
+        let placeholders = vec![placeholder; columns.len()].join(", ");
+
+        let loader = Self {
+            path,
+            insert: format!("INSERT INTO {table} VALUES ({placeholders})"),
+            columns: columns.len(),
+            rows: 0,
+        };
+
+        let create = format!("CREATE TABLE {table} ({names})");
+        connection
+            .execute(create.as_str())
+            .await
+            .map_err(|error| loader.error(error))?;
+
+        Ok(loader)
+    }
+
+    /// Insert a row, missing values are `NULL`
+    async fn insert(
+        &mut self,
+        connection: &mut SqliteConnection,
+        row: impl IntoIterator<Item = Field>,
+    ) -> Result<(), SQLError> {
+        let mut values = row.into_iter();
+        let mut arguments = SqliteArguments::default();
+        for _ in 0..self.columns {
+            let result = match values.next() {
+                None | Some(Field::Json(Value::Null)) => arguments.add(None::<String>),
+                Some(Field::Json(Value::Bool(value))) => arguments.add(value),
+                Some(Field::Json(Value::Number(number))) => match number.as_i64() {
+                    Some(value) => arguments.add(value),
+                    None => arguments.add(number.as_f64()),
+                },
+                Some(Field::Json(Value::String(value))) => arguments.add(value),
+                // Nested values are kept as JSON text
+                Some(Field::Json(value)) => arguments.add(value.to_string()),
+                Some(Field::Bytes(value)) => arguments.add(value),
+            };
+            result.map_err(|error| self.error(sqlx::Error::Encode(error)))?;
+        }
+
+        connection
+            .execute(sqlx::query_with(&self.insert, arguments))
+            .await
+            .map_err(|error| self.error(error))?;
+        self.rows += 1;
+
+        Ok(())
+    }
+
+    fn error(&self, error: sqlx::Error) -> SQLError {
+        SQLError::SqlX((format!("load \"{}\"", self.path.display()), error))
+    }
+}
+
+/// Header row is column names, all values are strings.
+///
+/// Columns are TEXT like `.import` of `sqlite3` does, so numbers compare with them as usual.
+/// Values which aren't valid UTF-8 are stored as TEXT bytes and matched as such.
+async fn load_delimited(
+    connection: &mut SqliteConnection,
+    table: &str,
+    delimiter: u8,
+    path: &Path,
+) -> Result<u64, SQLError> {
+    let csv_error =
+        |error: csv::Error| SQLError::Io((format!("read \"{}\"", path.display()), error.into()));
+
+    let file = File::open(path).map_err(|error| read_error(path, error))?;
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(BufReader::new(file));
+
+    let headers = reader.byte_headers().map_err(csv_error)?;
+    let headers = headers
+        .iter()
+        .map(String::from_utf8_lossy)
+        .collect::<Vec<_>>();
+    let columns = unique_columns(headers.iter().map(AsRef::as_ref));
+    let mut loader = Loader::create(connection, table, &columns, "TEXT", path).await?;
+
+    let mut truncated = 0;
+    for record in reader.byte_records() {
+        let record = record.map_err(csv_error)?;
+        if record.len() > columns.len() {
+            truncated += 1;
+        }
+
+        let row = record
+            .iter()
+            .map(|value| match std::str::from_utf8(value) {
+                Ok(value) => Field::Json(Value::String(value.to_owned())),
+                Err(_) => Field::Bytes(value.to_owned()),
+            })
+            .collect::<Vec<_>>();
+        loader.insert(connection, row).await?;
+    }
+
+    if truncated > 0 {
+        log::warn!(
+            "{truncated} rows of {} have more values than columns, extra values are dropped",
+            path.display()
+        );
+    }
+
+    Ok(loader.rows)
+}
+
+/// Keys of all objects are column names in order they are found first.
+///
+/// The file is read twice: for keys and for rows. Columns have no type affinity,
+/// so JSON numbers and strings keep their types.
+async fn load_ndjson(
+    connection: &mut SqliteConnection,
+    table: &str,
+    path: &Path,
+) -> Result<u64, SQLError> {
+    let mut keys = vec![];
+    let mut seen = HashSet::new();
+    for object in ndjson_objects(path, true)? {
+        for key in object?.keys() {
+            if seen.insert(key.clone()) {
+                keys.push(key.clone());
+            }
+        }
+    }
+
+    let columns = unique_columns(keys.iter().map(String::as_str));
+    let mut loader = Loader::create(connection, table, &columns, "", path).await?;
+
+    for object in ndjson_objects(path, false)? {
+        let mut object = object?;
+        let row = keys
+            .iter()
+            .map(|key| Field::Json(object.remove(key).unwrap_or(Value::Null)))
+            .collect::<Vec<_>>();
+        loader.insert(connection, row).await?;
+    }
+
+    Ok(loader.rows)
+}
+
+/// JSON objects on lines of the file, other lines are skipped with a warning if requested
+fn ndjson_objects(
+    path: &Path,
+    warn: bool,
+) -> Result<impl Iterator<Item = Result<Map<String, Value>, SQLError>> + '_, SQLError> {
+    let file = File::open(path).map_err(|error| read_error(path, error))?;
+
+    Ok(BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter_map(move |(index, line)| {
+            let line = match line {
+                Ok(line) => line,
+                Err(error) => return Some(Err(read_error(path, error))),
+            };
+            if line.trim().is_empty() {
+                return None;
+            }
+
+            match serde_json::from_str::<Value>(&line) {
+                Ok(Value::Object(object)) => Some(Ok(object)),
+                Ok(_) => {
+                    if warn {
+                        log::warn!(
+                            "Skip line {} of {}: not a JSON object",
+                            index + 1,
+                            path.display()
+                        );
+                    }
+                    None
+                }
+                Err(error) => {
+                    if warn {
+                        log::warn!("Skip line {} of {}: {error}", index + 1, path.display());
+                    }
+                    None
+                }
+            }
+        }))
+}
+
+#[inline]
+fn read_error(path: &Path, error: std::io::Error) -> SQLError {
+    SQLError::Io((format!("read \"{}\"", path.display()), error))
+}
+
+/// Empty names are replaced with `column_<N>`, duplicates get `_<N>` suffix.
+///
+/// Names are compared case-insensitive as SQLite does.
+fn unique_columns<'a>(names: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut seen = HashSet::new();
+
+    names
+        .enumerate()
+        .map(|(index, name)| {
+            let name = match name.trim() {
+                "" => format!("column_{}", index + 1),
+                name => name.to_owned(),
+            };
+
+            let mut unique = name.clone();
+            let mut suffix = 1;
+            while !seen.insert(unique.to_lowercase()) {
+                suffix += 1;
+                unique = format!("{name}_{suffix}");
+            }
+            unique
+        })
+        .collect()
+}
@@ -0,0 +1,294 @@
+use std::borrow::Cow;
+use std::fmt::{self, Write as _};
+
+use serde_json::Value;
+
+use crate::cell_to_string::{Cell, CellMatch};
+use crate::error::SQLError;
+use crate::pattern::Pattern;
+
+/// Step from a JSON value to its child
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+/// Step of a JSON path expression
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Selector {
+    Key(String),
+    Index(usize),
+    /// `.*` or `[*]`, any key or index
+    Any,
+    /// `..`, any number of steps
+    Descendants,
+}
+
+/// JSON path expression to restrict search inside JSON cells, e.g. `$.user.emails[*]`.
+///
+/// Supports keys (`.key`, `["key"]`), indexes (`[2]`), wildcards (`.*`, `[*]`)
+/// and recursive descent (`..key`). Everything under a selected value is searched.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsonPath {
+    selectors: Vec<Selector>,
+}
+
+impl JsonPath {
+    pub fn parse(path: &str) -> Result<Self, SQLError> {
+        let error = |message: &str| SQLError::Configuration(format!("JSON path {path}: {message}"));
+
+        let mut rest = path
+            .trim()
+            .strip_prefix('$')
+            .ok_or_else(|| error("must start with $"))?;
+        let mut selectors = vec![];
+
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix("..") {
+                selectors.push(Selector::Descendants);
+                // `..key` is the same as `..` and `.key`
+                rest = if after.starts_with('[') {
+                    after
+                } else {
+                    &rest[1..]
+                };
+            } else if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                let key = &after[..end];
+                selectors.push(match key {
+                    "" => return Err(error("empty key")),
+                    "*" => Selector::Any,
+                    key => Selector::Key(key.to_owned()),
+                });
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let end = after.find(']').ok_or_else(|| error("missing ]"))?;
+                let inner = after[..end].trim();
+                selectors.push(if inner == "*" {
+                    Selector::Any
+                } else if let Ok(index) = inner.parse() {
+                    Selector::Index(index)
+                } else if let Some(key) = inner
+                    .strip_prefix('"')
+                    .and_then(|inner| inner.strip_suffix('"'))
+                    .or_else(|| {
+                        inner
+                            .strip_prefix('\'')
+                            .and_then(|inner| inner.strip_suffix('\''))
+                    })
+                {
+                    Selector::Key(key.to_owned())
+                } else {
+                    return Err(error("expected index, quoted key or *"));
+                });
+                rest = &after[end + 1..];
+            } else {
+                return Err(error("expected . or ["));
+            }
+        }
+
+        if selectors.last() == Some(&Selector::Descendants) {
+            return Err(error("ends with .."));
+        }
+
+        Ok(Self { selectors })
+    }
+
+    /// Path selects the value at steps or one of its parents
+    fn selects(&self, steps: &[Step]) -> bool {
+        select_prefix(&self.selectors, steps)
+    }
+}
+
+/// Selectors match steps from the beginning, the rest of steps is under a selected value
+fn select_prefix(selectors: &[Selector], steps: &[Step]) -> bool {
+    let Some((selector, selectors)) = selectors.split_first() else {
+        return true;
+    };
+
+    if let Selector::Descendants = selector {
+        return (0..=steps.len()).any(|skip| select_prefix(selectors, &steps[skip..]));
+    }
+
+    let Some((step, steps)) = steps.split_first() else {
+        return false;
+    };
+    let is_selected = match (selector, step) {
+        (Selector::Any, _) => true,
+        (Selector::Key(key), Step::Key(step)) => key == step,
+        (Selector::Index(index), Step::Index(step)) => index == step,
+        _ => false,
+    };
+
+    is_selected && select_prefix(selectors, steps)
+}
+
+/// Parse text cell as JSON, only objects and arrays are taken as documents
+pub(crate) fn parse_document(value: &str) -> Option<Value> {
+    let trimmed = value.trim_start();
+    if !trimmed.starts_with(['{', '[']) {
+        return None;
+    }
+
+    serde_json::from_str(value)
+        .ok()
+        .filter(|document: &Value| document.is_object() || document.is_array())
+}
+
+/// Match keys and scalar values of a document in order, the first match is returned with its path.
+///
+/// When paths are given, only keys and values under them are matched.
+pub(crate) fn find_in_document(document: &Value, pattern: &Pattern) -> Option<CellMatch> {
+    let mut steps = vec![];
+    find_in_value(document, pattern, &mut steps)
+}
+
+fn find_in_value(value: &Value, pattern: &Pattern, steps: &mut Vec<Step>) -> Option<CellMatch> {
+    let scalar = match value {
+        Value::Null => return None,
+        Value::Bool(value) => Cow::Owned(value.to_string()),
+        Value::Number(value) => Cow::Owned(value.to_string()),
+        Value::String(value) => Cow::Borrowed(value.as_str()),
+        Value::Array(values) => {
+            for (index, value) in values.iter().enumerate() {
+                steps.push(Step::Index(index));
+                let found = find_in_value(value, pattern, steps);
+                steps.pop();
+                if found.is_some() {
+                    return found;
+                }
+            }
+            return None;
+        }
+        Value::Object(values) => {
+            for (key, value) in values {
+                steps.push(Step::Key(key.clone()));
+                let found =
+                    find_key(key, pattern, steps).or_else(|| find_in_value(value, pattern, steps));
+                steps.pop();
+                if found.is_some() {
+                    return found;
+                }
+            }
+            return None;
+        }
+    };
+
+    find_key(&scalar, pattern, steps)
+}
+
+/// Match a key or a scalar at the path
+fn find_key(value: &str, pattern: &Pattern, steps: &[Step]) -> Option<CellMatch> {
+    let paths = &pattern.options().json_paths;
+    if !paths.is_empty() && !paths.iter().any(|path| path.selects(steps)) {
+        return None;
+    }
+
+    let mut found = Cell::Value(Cow::Borrowed(value)).find_value(pattern)?;
+    found.json_path = Some(format_path(steps));
+    Some(found)
+}
+
+/// Path in `$.user.emails[2]` form, keys which aren't identifiers are quoted
+fn format_path(steps: &[Step]) -> String {
+    let mut path = String::from("$");
+    for step in steps {
+        let _ = match step {
+            Step::Key(key) => write_key(&mut path, key),
+            Step::Index(index) => write!(path, "[{index}]"),
+        };
+    }
+    path
+}
+
+fn write_key(writer: &mut impl fmt::Write, key: &str) -> fmt::Result {
+    let is_identifier = key
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    if is_identifier {
+        write!(writer, ".{key}")
+    } else {
+        write!(writer, "[{}]", Value::String(key.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::{PatternKind, PatternOptions};
+
+    fn pattern(paths: &[&str]) -> Pattern {
+        let options = PatternOptions {
+            json_cells: true,
+            json_paths: paths
+                .iter()
+                .map(|path| JsonPath::parse(path).unwrap())
+                .collect(),
+            ..PatternOptions::default()
+        };
+        Pattern::new("needle", &PatternKind::Fixed, options).unwrap()
+    }
+
+    fn found_path(value: &str, paths: &[&str]) -> Option<String> {
+        Cell::Value(Cow::Borrowed(value))
+            .find(&pattern(paths))
+            .map(|found| found.json_path.unwrap_or_default())
+    }
+
+    #[test]
+    fn paths_are_parsed() {
+        let path = JsonPath::parse("$.user['a.b'][2].*..id[*]").unwrap();
+        assert_eq!(
+            path.selectors,
+            [
+                Selector::Key("user".into()),
+                Selector::Key("a.b".into()),
+                Selector::Index(2),
+                Selector::Any,
+                Selector::Descendants,
+                Selector::Key("id".into()),
+                Selector::Any,
+            ]
+        );
+        assert_eq!(JsonPath::parse("$").unwrap().selectors, []);
+
+        for invalid in ["user", "$.", "$[1", "$[key]", "$..", "$user"] {
+            assert!(JsonPath::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn keys_and_values_are_matched_with_paths() {
+        let document = r#"{"user": {"name": "x", "emails": ["a", "needle@b"]}, "needle key": 1}"#;
+
+        assert_eq!(
+            found_path(document, &[]).as_deref(),
+            Some("$.user.emails[1]")
+        );
+        assert_eq!(
+            found_path(r#"{"needle key": 1}"#, &[]).as_deref(),
+            Some(r#"$["needle key"]"#)
+        );
+        assert_eq!(
+            found_path(document, &["$..emails[*]"]).as_deref(),
+            Some("$.user.emails[1]")
+        );
+        assert_eq!(found_path(document, &["$.user.name", "$.other"]), None);
+        assert_eq!(
+            found_path(document, &["$.user.name", "$.*"]).as_deref(),
+            Some("$.user.emails[1]")
+        );
+    }
+
+    #[test]
+    fn cells_which_arent_documents_are_excluded_by_paths() {
+        assert_eq!(found_path("a needle", &[]).as_deref(), Some(""));
+        assert_eq!(found_path(r#""needle""#, &[]).as_deref(), Some(""));
+        assert_eq!(found_path("a needle", &["$..*"]), None);
+        assert_eq!(found_path(r#"{"broken": "needle""#, &["$..*"]), None);
+    }
+}
@@ -0,0 +1,45 @@
+//! Grep SQL databases for fun and profit.
+//!
+//! Every cell of tables or queries is converted to a string and matched with a [`Pattern`].
+//! Use [`Search::builder`] to configure a search and get a stream of [`Event`]s
+//! or pass them to an [`OutputSink`].
+
+mod archive;
+mod carve;
+mod cell_to_string;
+mod connection;
+mod decode;
+mod dump;
+mod encodings;
+mod error;
+mod filter;
+mod import;
+mod json_cells;
+mod limits;
+mod matching;
+mod output;
+mod pattern;
+mod progress;
+mod protobuf;
+mod query;
+mod raw;
+mod search;
+mod select;
+mod sqlite_file;
+mod stats;
+mod wal;
+
+pub use connection::{CipherKey, ConnectionOptions};
+pub use decode::Decoding;
+pub use encodings::{EncodedPattern, Encoding};
+pub use error::{Level, QueryError, SQLError};
+pub use import::DataFormat;
+pub use json_cells::JsonPath;
+pub use output::{CollectSink, CsvSink, JsonSink, OutputSink, TextSink};
+pub use pattern::{
+    BinaryPattern, FixedPattern, Pattern, PatternKind, PatternOptions, RegexPattern,
+};
+pub use search::{
+    Engine, Event, EventStream, Location, Match, Search, SearchBuilder, SearchOptions,
+};
+pub use stats::{SourceStats, Stats};
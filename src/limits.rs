@@ -0,0 +1,89 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::stats::SourceStats;
+
+/// Limits to stop scanning early, shared between all sources
+pub(crate) struct Limits {
+    /// Maximum number of matches per source
+    pub max_count: Option<u64>,
+    /// Maximum number of matches for all sources
+    pub max_total_count: Option<u64>,
+    /// Maximum number of rows to fetch per source
+    pub max_rows: Option<u64>,
+    /// Maximum time to run a single source
+    pub timeout: Option<Duration>,
+    /// Time all sources must finish
+    pub total_deadline: Option<Instant>,
+    /// Maximum number of errors per source
+    pub max_errors: Option<u64>,
+    total_matches: AtomicU64,
+    /// Search is aborted
+    stopped: AtomicBool,
+}
+
+impl Limits {
+    pub fn new(
+        max_count: Option<u64>,
+        max_total_count: Option<u64>,
+        max_rows: Option<u64>,
+    ) -> Self {
+        Self {
+            max_count,
+            max_total_count,
+            max_rows,
+            timeout: None,
+            total_deadline: None,
+            max_errors: None,
+            total_matches: AtomicU64::new(0),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    /// Time source which starts now must finish
+    pub fn deadline(&self) -> Option<Instant> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+
+        match (deadline, self.total_deadline) {
+            (Some(deadline), Some(total_deadline)) => Some(deadline.min(total_deadline)),
+            (deadline, total_deadline) => deadline.or(total_deadline),
+        }
+    }
+
+    /// Reserve a match for a source, `false` when no more matches are allowed
+    pub fn acquire_match(&self, stats: &SourceStats) -> bool {
+        if self.max_count.is_some_and(|max| stats.matches >= max) {
+            return false;
+        }
+
+        match self.max_total_count {
+            None => true,
+            Some(max) => self
+                .total_matches
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |total| {
+                    (total < max).then_some(total + 1)
+                })
+                .is_ok(),
+        }
+    }
+
+    /// Check if source has too many errors to continue
+    pub fn is_error_budget_exceeded(&self, stats: &SourceStats) -> bool {
+        self.max_errors.is_some_and(|max| stats.errors() > max)
+    }
+
+    /// Stop all sources, e.g. when nobody is interested in events anymore
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    /// Check if source should stop fetching rows
+    pub fn is_done(&self, stats: &SourceStats) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+            || self.max_rows.is_some_and(|max| stats.rows >= max)
+            || self.max_count.is_some_and(|max| stats.matches >= max)
+            || self
+                .max_total_count
+                .is_some_and(|max| self.total_matches.load(Ordering::SeqCst) >= max)
+    }
+}
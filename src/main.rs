@@ -1,29 +1,28 @@
 mod args;
-mod cell_to_string;
-mod error;
-mod matching;
-mod pattern;
-mod query;
-mod select;
+mod config;
 
 use std::io::stdin;
+use std::io::stdout;
+use std::io::ErrorKind;
+use std::io::IsTerminal as _;
 use std::io::Read;
 
-use error::Level;
-use error::SQLError;
-use matching::sqlite_check_rows;
-use pattern::Pattern;
-use query::{prepare_queries, SelectVariant};
-use sqlparser::dialect::SQLiteDialect;
-
-use sqlx::{sqlite::SqliteConnectOptions, Executor as _, Pool, Row as _, Sqlite, SqlitePool};
+use args::{InputFormat, OutputFormat, PatternEncoding, ScanEngine};
+use sqlgrep::{
+    CipherKey, ConnectionOptions, CsvSink, DataFormat, Encoding, Engine, JsonPath, JsonSink, Level,
+    Pattern, PatternKind, PatternOptions, SQLError, Search, SearchOptions, TextSink,
+};
 
 #[tokio::main()]
 async fn main() {
-    let args = args::parse_args();
+    let (mut args, matches) = args::parse_args();
 
     setup_logging(args.verbose.level());
 
+    if let Err(error) = config::apply_config(&mut args, &matches) {
+        std::process::exit(error.report(Level::Error));
+    }
+
     let pattern = create_pattern(&args.pattern)
         .unwrap_or_else(|error| std::process::exit(error.report(Level::Error)));
 
@@ -32,17 +31,93 @@ async fn main() {
         Err(error) => std::process::exit(error.report(Level::Error)),
     };
 
-    match process_sqlite_database(
-        args.database_uri,
-        pattern,
-        args.query.table,
-        queries,
-        args.query.ignore_non_readonly,
-    )
-    .await
-    {
-        Ok(()) => {}
+    let key = read_key(&args.connection)
+        .unwrap_or_else(|error| std::process::exit(error.report(Level::Error)));
+
+    let database = match (&args.connection.from_dump, args.database_uri) {
+        (Some(dump), _) => dump.to_string_lossy().into_owned(),
+        (None, Some(database_uri)) => database_uri,
+        (None, None) => unreachable!("clap requires database URI or dump"),
+    };
+
+    let show_progress =
+        !args.scan.no_progress && log::log_enabled!(Level::Info) && std::io::stderr().is_terminal();
+
+    let options = SearchOptions {
+        ignore_non_readonly: args.query.ignore_non_readonly,
+        prefilter: args.query.prefilter,
+        jobs: args.scan.jobs,
+        sort: args.scan.sort,
+        progress: show_progress,
+        max_count: args.scan.max_count,
+        max_total_count: args.scan.max_total_count,
+        max_rows: args.scan.max_rows,
+        sample: args.scan.sample,
+        timeout: args.scan.timeout,
+        total_timeout: args.scan.total_timeout,
+        max_errors: Some(args.scan.max_errors),
+        carve: args.scan.carve,
+        wal_history: args.scan.wal_history,
+        engine: match args.scan.engine {
+            ScanEngine::Sqlx => Engine::Sqlx,
+            ScanEngine::Raw => Engine::Raw,
+        },
+        connection: ConnectionOptions {
+            immutable: args.connection.no_immutable.then_some(false),
+            include_wal: args.connection.include_wal.then_some(true),
+            key,
+            cipher_compatibility: args.connection.cipher_compatibility,
+            vfs: args.connection.vfs,
+            pragmas: args.connection.pragma,
+            from_dump: args.connection.from_dump.is_some().then_some(true),
+            data_format: args.connection.data_format.map(|format| match format {
+                InputFormat::Csv => DataFormat::Csv,
+                InputFormat::Tsv => DataFormat::Tsv,
+                InputFormat::Ndjson => DataFormat::Ndjson,
+            }),
+        },
+    };
+
+    let search = Search::builder()
+        .database(database)
+        .pattern(pattern)
+        .tables(args.query.table)
+        .queries(queries)
+        .options(options)
+        .build()
+        .unwrap_or_else(|error| std::process::exit(error.report(Level::Error)));
+
+    let summary = args.scan.stats;
+    let result = match args.output.format {
+        OutputFormat::Text => {
+            search
+                .run_with_sink(&mut TextSink::new(stdout(), summary))
+                .await
+        }
+        OutputFormat::Json => {
+            search
+                .run_with_sink(&mut JsonSink::new(stdout(), summary))
+                .await
+        }
+        OutputFormat::Csv => {
+            search
+                .run_with_sink(&mut CsvSink::new(stdout(), summary))
+                .await
+        }
+    };
+
+    let stats = match result {
+        Ok(stats) => stats,
+        // Output is closed, e.g. piped to `head`
+        Err(SQLError::Io((_, error))) if error.kind() == ErrorKind::BrokenPipe => {
+            std::process::exit(0)
+        }
         Err(error) => std::process::exit(error.report(Level::Error)),
+    };
+
+    match stats.failed() {
+        0 => {}
+        failed => std::process::exit(SQLError::SourcesFailed(failed).report(Level::Error)),
     }
 }
 
@@ -58,91 +133,71 @@ fn setup_logging(verbosity_level: i16) {
 }
 
 fn create_pattern(options: &args::PatternArgs) -> Result<Pattern, SQLError> {
-    let kind = if options.fixed {
-        pattern::PatternKind::Fixed
+    let kind = if !options.encodings.is_empty() {
+        PatternKind::Encoded(encodings(&options.encodings))
+    } else if options.fixed {
+        PatternKind::Fixed
+    } else if options.hex {
+        PatternKind::Hex
+    } else if options.byte_regex {
+        PatternKind::ByteRegex
     } else {
-        pattern::PatternKind::Regex
+        PatternKind::Regex
     };
 
+    let json_paths = options
+        .json_path
+        .iter()
+        .map(|path| JsonPath::parse(path))
+        .collect::<Result<Vec<_>, _>>()?;
+
     Pattern::new(
         options.pattern.as_str(),
         &kind,
-        pattern::PatternOptions {
+        PatternOptions {
             case_insensitive: options.case_insensitive,
             whole_string: options.whole_string,
+            blobs: options.blobs,
+            decode_depth: options.decode_depth,
+            json_cells: options.json_cells || !json_paths.is_empty(),
+            json_paths,
+            protobuf: options.protobuf,
         },
     )
 }
 
-async fn process_sqlite_database(
-    database_uri: String,
-    pattern: Pattern,
-    tables: Vec<String>,
-    queries: Vec<String>,
-    ignore_non_read: bool,
-) -> Result<(), SQLError> {
-    let dialect = SQLiteDialect {};
-
-    let options: SqliteConnectOptions = database_uri
-        .parse::<SqliteConnectOptions>()
-        .map(|options| options.read_only(true).immutable(true))
-        .map_err(|error| SQLError::SqlX(("Database URI".into(), error)))?;
-
-    let db = SqlitePool::connect_with(options)
-        .await
-        .map_err(|error| SQLError::SqlX(("Database connection".into(), error)))?;
-
-    let select_variant = prepare_queries(
-        tables.into_iter(),
-        queries.into_iter(),
-        &dialect,
-        ignore_non_read,
-    )?;
-
-    let queries = match select_variant {
-        SelectVariant::Queries(queries) => queries,
-        SelectVariant::WholeDB => {
-            let tables = sqlite_select_tables(&db).await?;
-            let select_variant = prepare_queries(
-                tables.into_iter(),
-                vec![].into_iter(),
-                &dialect,
-                ignore_non_read,
-            )?;
-            match select_variant {
-                SelectVariant::WholeDB => vec![],
-                SelectVariant::Queries(queries) => queries,
+fn encodings(options: &[PatternEncoding]) -> Vec<Encoding> {
+    let mut encodings = vec![];
+    for encoding in options {
+        let expanded: &[Encoding] = match encoding {
+            PatternEncoding::All => &Encoding::ALL,
+            PatternEncoding::Base64 => &[Encoding::Base64],
+            PatternEncoding::Hex => &[Encoding::Hex],
+            PatternEncoding::Url => &[Encoding::Url],
+            PatternEncoding::Utf16 => &[Encoding::Utf16Le, Encoding::Utf16Be],
+        };
+        for encoding in expanded {
+            if !encodings.contains(encoding) {
+                encodings.push(*encoding);
             }
         }
-    };
-
-    for (query_id, query) in queries {
-        sqlite_check_rows(&db, query_id.as_str(), query.as_str(), &pattern).await;
     }
-
-    Ok(())
+    encodings
 }
 
-async fn sqlite_select_tables(db: &Pool<Sqlite>) -> Result<Vec<String>, SQLError> {
-    let select_query = "SELECT name FROM sqlite_schema WHERE type = 'table'";
+fn read_key(options: &args::ConnectionArgs) -> Result<Option<CipherKey>, SQLError> {
+    let Some(filename) = &options.key_file else {
+        return options.key.as_deref().map(CipherKey::parse).transpose();
+    };
 
-    log::debug!("Execute query: {select_query}");
+    let key = std::fs::read_to_string(filename)
+        .map_err(|error| SQLError::Io((format!("read \"{}\"", filename.display()), error)))?;
 
-    let result = db
-        .fetch_all(select_query)
-        .await
-        .map_err(|error| SQLError::SqlX(("fetch tables".into(), error)))?;
+    // Editors usually add a new line at the end of file
+    let key = key.strip_suffix('\n').unwrap_or(&key);
+    let key = key.strip_suffix('\r').unwrap_or(key);
 
-    Ok(result
-        .into_iter()
-        .filter_map(|row| match row.try_get::<String, &str>("name") {
-            Ok(value) => Some(value),
-            Err(error) => {
-                SQLError::SqlX(("fetch tables".into(), error)).report(Level::Warn);
-                None
-            }
-        })
-        .collect())
+    CipherKey::parse(key).map(Some)
 }
 
 fn read_queries<R: Read>(
@@ -1,75 +1,248 @@
-use crate::cell_to_string::sqlite_cell_to_string;
+use crate::cell_to_string::{sqlite_cell_to_string, Cell};
 use crate::error::Level;
-use crate::{Pattern, SQLError};
+use crate::error::SQLError;
+use crate::limits::Limits;
+use crate::pattern::Pattern;
+use crate::progress::SourceProgress;
+use crate::search::{Emitter, Event, Match};
+use crate::stats::SourceStats;
 
-use sqlx::{Column, Executor, Pool, Row, Sqlite};
+use std::time::Instant;
 
-pub async fn sqlite_check_rows(
+use sqlx::{Column, Executor, Pool, Row, Sqlite, SqliteConnection};
+
+/// Number of SQLite virtual machine instructions between timeout checks
+const PROGRESS_HANDLER_OPS: i32 = 10_000;
+
+pub(crate) async fn sqlite_check_rows(
     db: &Pool<Sqlite>,
     query_id: &str,
     select_query: &str,
     pattern: &Pattern,
-) {
+    progress: &SourceProgress,
+    limits: &Limits,
+    output: &mut Emitter,
+) -> SourceStats {
     use futures::TryStreamExt;
-    use std::sync::atomic::AtomicU64;
     use std::sync::atomic::Ordering;
 
+    let started = Instant::now();
+    let mut stats = SourceStats::default();
+
     log::debug!("{query_id}: {select_query}");
 
-    let mut rows = db.fetch(select_query);
+    let deadline = limits.deadline();
+    if deadline.is_some_and(|deadline| deadline <= started) {
+        output
+            .emit(Event::Error(SQLError::Timeout(query_id.to_owned())))
+            .await;
+        stats.timed_out = true;
+        return stats;
+    }
+
+    let mut connection = match db.acquire().await {
+        Ok(connection) => connection,
+        Err(error) => {
+            output
+                .emit(Event::Error(SQLError::SqlX((query_id.to_owned(), error))))
+                .await;
+            stats.failed = true;
+            return stats;
+        }
+    };
+
+    if let Some(deadline) = deadline {
+        // Interrupts statement which doesn't return rows in time
+        set_deadline(&mut connection, deadline, query_id).await;
+    }
+
+    let mut rows = connection.fetch(select_query);
 
-    let row_counter: AtomicU64 = AtomicU64::new(0);
     loop {
-        let row_idx = row_counter.load(Ordering::SeqCst);
+        if limits.is_done(&stats) {
+            log::debug!("{query_id}: limit reached");
+            break;
+        }
+
+        if output.is_closed() {
+            log::debug!("{query_id}: events aren't received anymore");
+            break;
+        }
+
+        let row_idx = progress.rows.load(Ordering::SeqCst);
+
+        if deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+            output
+                .emit(Event::Error(SQLError::Timeout(format!(
+                    "{query_id}::{row_idx}"
+                ))))
+                .await;
+            stats.timed_out = true;
+            break;
+        }
+
+        if limits.is_error_budget_exceeded(&stats) {
+            log::warn!("{query_id}: too many errors, skip the rest");
+            stats.failed = true;
+            break;
+        }
 
         let row = match rows.try_next().await {
             Ok(None) => break,
             Ok(Some(row)) => row,
             Err(error) => {
-                SQLError::SqlX((format!("{query_id}::{row_idx}"), error)).report(Level::Warn);
-                continue;
+                if deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+                    output
+                        .emit(Event::Error(SQLError::Timeout(format!(
+                            "{query_id}::{row_idx}"
+                        ))))
+                        .await;
+                    stats.timed_out = true;
+                    break;
+                }
+
+                output
+                    .emit(Event::Error(SQLError::SqlX((
+                        format!("{query_id}::{row_idx}"),
+                        error,
+                    ))))
+                    .await;
+                stats.read_errors += 1;
+
+                // Statement can't continue after an error, cells are decoded later per row
+                stats.failed = true;
+                break;
+            }
+        };
+
+        let matches = stats.matches;
+        sqlite_process_row(row_idx, &row, query_id, pattern, limits, &mut stats, output).await;
+        progress
+            .matches
+            .fetch_add(stats.matches - matches, Ordering::Relaxed);
+        progress.rows.fetch_add(1, Ordering::SeqCst);
+        stats.rows += 1;
+    }
+
+    drop(rows);
+
+    if deadline.is_some() {
+        // Connection returns to the pool and must not interrupt next query
+        let removed = match connection.lock_handle().await {
+            Ok(mut handle) => {
+                handle.remove_progress_handler();
+                true
+            }
+            Err(error) => {
+                SQLError::SqlX((query_id.to_owned(), error)).report(Level::Warn);
+                false
             }
         };
 
-        sqlite_process_row(row_idx, &row, query_id, pattern);
-        row_counter.fetch_add(1, Ordering::SeqCst);
+        if !removed {
+            connection.close_on_drop();
+        }
+    }
+
+    stats.elapsed = started.elapsed();
+    stats
+}
+
+async fn set_deadline(connection: &mut SqliteConnection, deadline: Instant, query_id: &str) {
+    match connection.lock_handle().await {
+        Ok(mut handle) => {
+            handle.set_progress_handler(PROGRESS_HANDLER_OPS, move || Instant::now() < deadline);
+        }
+        Err(error) => {
+            SQLError::SqlX((query_id.to_owned(), error)).report(Level::Warn);
+        }
     }
 }
 
-fn sqlite_process_row(
+async fn sqlite_process_row(
     row_idx: u64,
     row: &sqlx::sqlite::SqliteRow,
     query_id: &str,
     pattern: &Pattern,
+    limits: &Limits,
+    stats: &mut SourceStats,
+    output: &mut Emitter,
 ) {
     use sqlx::TypeInfo;
     let columns = row.columns();
+    // Numbers and dates of the row are formatted into the same buffer
+    let mut buffer = String::new();
     for column in columns {
         let index = column.ordinal();
-        let column_name = column.name().to_owned();
+        let column_name = column.name();
         let column_type = column.type_info().name();
-        let row_id = format!("{query_id}::{row_idx}::{column_name}");
+        // Context is only formatted for errors
+        let row_id = || format!("{query_id}::{row_idx}::{column_name}");
 
         let value_ref = match row.try_get_raw(index) {
             Ok(value_ref) => value_ref,
             Err(error) => {
-                SQLError::SqlX((row_id, error)).report(Level::Warn);
+                output
+                    .emit(Event::Error(SQLError::SqlX((row_id(), error))))
+                    .await;
+                stats.read_errors += 1;
                 continue;
             }
         };
 
-        let value_str = match sqlite_cell_to_string(value_ref) {
-            Ok(Some(value_str)) => value_str,
-            Ok(None) => continue,
+        stats.cells += 1;
+
+        let cell = match sqlite_cell_to_string(value_ref, &mut buffer) {
+            Ok(Cell::Null) => {
+                stats.skipped_null += 1;
+                continue;
+            }
+            Ok(Cell::Blob(_)) if !pattern.options().matches_blobs() => {
+                stats.skipped_blob += 1;
+                continue;
+            }
+            Ok(Cell::Unknown) => {
+                stats.skipped_unknown += 1;
+                let error_context = format!("{} cell type {column_type}", row_id());
+                output
+                    .emit(Event::Error(SQLError::ConvertCell((
+                        error_context,
+                        "Unknown type".into(),
+                    ))))
+                    .await;
+                continue;
+            }
             Err(error) => {
-                let error_context = format!("{row_id} cell type {column_type}");
-                SQLError::ConvertCell((error_context, error)).report(Level::Warn);
+                stats.conversion_errors += 1;
+                let error_context = format!("{} cell type {column_type}", row_id());
+                output
+                    .emit(Event::Error(SQLError::ConvertCell((error_context, error))))
+                    .await;
                 continue;
             }
+            Ok(cell) => cell,
         };
 
-        if pattern.is_match(&value_str) {
-            println!("{row_id} => {value_str}");
+        if let Some(found) = cell.find(pattern) {
+            if !limits.acquire_match(stats) {
+                return;
+            }
+            output
+                .emit(Event::Match(Match {
+                    source: query_id.to_owned(),
+                    row: row_idx,
+                    column: column_name.to_owned(),
+                    value: found.value,
+                    offset: found.offset,
+                    encoding: found.encoding,
+                    decoding: found.decoding,
+                    json_path: found.json_path,
+                    protobuf_path: found.protobuf_path,
+                    location: None,
+                    rowid: None,
+                }))
+                .await;
+            stats.matches += 1;
         }
     }
 }
@@ -0,0 +1,316 @@
+use std::io::Write;
+
+use serde_json::json;
+
+use crate::error::{Level, SQLError};
+use crate::search::{format_decoding, Event, Match};
+use crate::stats::{SourceStats, Stats};
+
+/// Receiver of search events.
+///
+/// Only [`OutputSink::found`] is required, errors are logged as warnings by default.
+pub trait OutputSink {
+    /// Table or query scan is started
+    fn source_start(&mut self, _source: &str) -> Result<(), SQLError> {
+        Ok(())
+    }
+
+    /// Cell matches the pattern
+    fn found(&mut self, found: Match) -> Result<(), SQLError>;
+
+    /// Error which doesn't stop the search
+    fn error(&mut self, error: SQLError) -> Result<(), SQLError> {
+        error.report(Level::Warn);
+        Ok(())
+    }
+
+    /// Table or query scan is finished
+    fn source_end(&mut self, _source: &str, _stats: &SourceStats) -> Result<(), SQLError> {
+        Ok(())
+    }
+
+    /// Search is finished
+    fn summary(&mut self, _stats: &Stats) -> Result<(), SQLError> {
+        Ok(())
+    }
+
+    /// Pass event to the matching method
+    fn event(&mut self, event: Event) -> Result<(), SQLError> {
+        match event {
+            Event::SourceStart(source) => self.source_start(&source),
+            Event::Match(found) => self.found(found),
+            Event::Error(error) => self.error(error),
+            Event::SourceEnd((source, stats)) => self.source_end(&source, &stats),
+        }
+    }
+}
+
+/// Collects matches in memory
+#[derive(Default)]
+pub struct CollectSink {
+    pub matches: Vec<Match>,
+}
+
+impl OutputSink for CollectSink {
+    fn found(&mut self, found: Match) -> Result<(), SQLError> {
+        self.matches.push(found);
+        Ok(())
+    }
+}
+
+/// Writes `<Table or Query>::<Row index>::<Column> => <Value>` lines
+///
+/// Path inside JSON document is added after the column as `::<JSON path>`,
+/// so is the path of field numbers inside protobuf message.
+/// Location in the database file is added after the column when known,
+/// so are the offset of a binary match in BLOB, the encoding of an encoded match
+/// and decodings applied to the value.
+pub struct TextSink<W: Write> {
+    writer: W,
+    summary: bool,
+}
+
+impl<W: Write> TextSink<W> {
+    /// Summary is printed to stderr if requested
+    pub fn new(writer: W, summary: bool) -> Self {
+        Self { writer, summary }
+    }
+}
+
+impl<W: Write> OutputSink for TextSink<W> {
+    fn found(&mut self, found: Match) -> Result<(), SQLError> {
+        writeln!(self.writer, "{found}").map_err(write_error)
+    }
+
+    fn summary(&mut self, stats: &Stats) -> Result<(), SQLError> {
+        self.writer.flush().map_err(write_error)?;
+
+        if self.summary {
+            stats
+                .report(&mut std::io::stderr().lock())
+                .map_err(write_error)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes every event as a JSON object on a separate line
+pub struct JsonSink<W: Write> {
+    writer: W,
+    summary: bool,
+}
+
+impl<W: Write> JsonSink<W> {
+    /// Summary is written as the last event if requested
+    pub fn new(writer: W, summary: bool) -> Self {
+        Self { writer, summary }
+    }
+
+    fn write(&mut self, value: &serde_json::Value) -> Result<(), SQLError> {
+        writeln!(self.writer, "{value}").map_err(write_error)
+    }
+}
+
+impl<W: Write> OutputSink for JsonSink<W> {
+    fn source_start(&mut self, source: &str) -> Result<(), SQLError> {
+        self.write(&json!({ "event": "source_start", "source": source }))
+    }
+
+    fn found(&mut self, found: Match) -> Result<(), SQLError> {
+        let mut value = json!({
+            "event": "match",
+            "source": found.source,
+            "row": found.row,
+            "column": found.column,
+            "value": found.value,
+        });
+        if let Some(json_path) = &found.json_path {
+            value["json_path"] = json_path.as_str().into();
+        }
+        if let Some(protobuf_path) = &found.protobuf_path {
+            value["protobuf_path"] = protobuf_path.as_str().into();
+        }
+        if let Some(location) = &found.location {
+            value["location"] = json!({ "page": location.page, "offset": location.offset });
+            if let Some(frame) = location.frame {
+                value["location"]["frame"] = frame.into();
+            }
+            if let Some(commit) = location.commit {
+                value["location"]["commit"] = commit.into();
+            }
+        }
+        if let Some(rowid) = found.rowid {
+            value["rowid"] = rowid.into();
+        }
+        if let Some(offset) = found.offset {
+            value["offset"] = offset.into();
+        }
+        if let Some(encoding) = found.encoding {
+            value["encoding"] = encoding.to_string().into();
+        }
+        if !found.decoding.is_empty() {
+            value["decoding"] = found
+                .decoding
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .into();
+        }
+        self.write(&value)
+    }
+
+    fn error(&mut self, error: SQLError) -> Result<(), SQLError> {
+        self.write(&json!({ "event": "error", "message": error.to_string() }))
+    }
+
+    fn source_end(&mut self, source: &str, stats: &SourceStats) -> Result<(), SQLError> {
+        let mut value = source_stats_json(stats);
+        value["event"] = "source_end".into();
+        value["source"] = source.into();
+        self.write(&value)
+    }
+
+    fn summary(&mut self, stats: &Stats) -> Result<(), SQLError> {
+        if self.summary {
+            let sources = stats
+                .sources
+                .iter()
+                .map(|(source, source_stats)| {
+                    let mut value = source_stats_json(source_stats);
+                    value["source"] = source.as_str().into();
+                    value
+                })
+                .collect::<Vec<_>>();
+
+            self.write(&json!({
+                "event": "summary",
+                "elapsed": stats.elapsed.as_secs_f64(),
+                "failed": stats.failed(),
+                "sources": sources,
+            }))?;
+        }
+
+        self.writer.flush().map_err(write_error)
+    }
+}
+
+/// Writes matches as CSV with `source,row,column,json_path,protobuf_path,value,location,rowid,offset,encoding,decoding` header
+pub struct CsvSink<W: Write> {
+    writer: csv::Writer<W>,
+    summary: bool,
+    header: bool,
+}
+
+impl<W: Write> CsvSink<W> {
+    /// Summary is printed to stderr if requested
+    pub fn new(writer: W, summary: bool) -> Self {
+        Self {
+            writer: csv::Writer::from_writer(writer),
+            summary,
+            header: false,
+        }
+    }
+}
+
+impl<W: Write> OutputSink for CsvSink<W> {
+    fn found(&mut self, found: Match) -> Result<(), SQLError> {
+        if !self.header {
+            self.writer
+                .write_record([
+                    "source",
+                    "row",
+                    "column",
+                    "json_path",
+                    "protobuf_path",
+                    "value",
+                    "location",
+                    "rowid",
+                    "offset",
+                    "encoding",
+                    "decoding",
+                ])
+                .map_err(csv_error)?;
+            self.header = true;
+        }
+
+        let row = found.row.to_string();
+        let location = found
+            .location
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        let rowid = found
+            .rowid
+            .map(|rowid| rowid.to_string())
+            .unwrap_or_default();
+        let offset = found
+            .offset
+            .map(|offset| offset.to_string())
+            .unwrap_or_default();
+        let encoding = found
+            .encoding
+            .map(|encoding| encoding.to_string())
+            .unwrap_or_default();
+        let decoding = format_decoding(&found.decoding);
+        self.writer
+            .write_record([
+                found.source.as_str(),
+                row.as_str(),
+                found.column.as_str(),
+                found.json_path.as_deref().unwrap_or_default(),
+                found.protobuf_path.as_deref().unwrap_or_default(),
+                found.value.as_str(),
+                location.as_str(),
+                rowid.as_str(),
+                offset.as_str(),
+                encoding.as_str(),
+                decoding.as_str(),
+            ])
+            .map_err(csv_error)
+    }
+
+    fn summary(&mut self, stats: &Stats) -> Result<(), SQLError> {
+        self.writer.flush().map_err(write_error)?;
+
+        if self.summary {
+            stats
+                .report(&mut std::io::stderr().lock())
+                .map_err(write_error)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn source_stats_json(stats: &SourceStats) -> serde_json::Value {
+    json!({
+        "rows": stats.rows,
+        "cells": stats.cells,
+        "matches": stats.matches,
+        "skipped_null": stats.skipped_null,
+        "skipped_blob": stats.skipped_blob,
+        "skipped_unknown": stats.skipped_unknown,
+        "conversion_errors": stats.conversion_errors,
+        "read_errors": stats.read_errors,
+        "timed_out": stats.timed_out,
+        "failed": stats.failed,
+        "elapsed": stats.elapsed.as_secs_f64(),
+    })
+}
+
+#[inline]
+fn write_error(error: std::io::Error) -> SQLError {
+    SQLError::Io(("write output".into(), error))
+}
+
+fn csv_error(error: csv::Error) -> SQLError {
+    if !error.is_io_error() {
+        return write_error(std::io::Error::other(error));
+    }
+    // Broken pipe is told apart by the kind of I/O error
+    match error.into_kind() {
+        csv::ErrorKind::Io(error) => write_error(error),
+        kind => write_error(std::io::Error::other(format!("{kind:?}"))),
+    }
+}
@@ -1,18 +1,82 @@
+use std::ops::Range;
+
+use aho_corasick::AhoCorasick;
+use memchr::memmem;
+
+use crate::encodings::{EncodedPattern, Encoding};
 use crate::error::SQLError;
+use crate::json_cells::JsonPath;
 
-pub(crate) enum PatternKind {
+pub enum PatternKind {
     Fixed,
     Regex,
+    /// Hex bytes to find in BLOB cells, e.g. `DEADBEEF`
+    Hex,
+    /// Regular expression over BLOB bytes, `\xNN` matches a single byte
+    ByteRegex,
+    /// Fixed string in plain and every given encoding, BLOB cells are matched as well
+    Encoded(Vec<Encoding>),
 }
-pub(crate) struct PatternOptions {
+
+#[derive(Default)]
+pub struct PatternOptions {
     pub case_insensitive: bool,
     pub whole_string: bool,
+    /// Match BLOB cells as bytes instead of skipping them
+    pub blobs: bool,
+    /// Number of nested encodings to decode in cell values, `0` to match values as is
+    pub decode_depth: usize,
+    /// Match keys and scalar values of JSON documents in TEXT cells separately
+    pub json_cells: bool,
+    /// Match only keys and values under these paths of JSON documents
+    pub json_paths: Vec<JsonPath>,
+    /// Parse BLOB cells as protobuf messages without schema and match their fields
+    pub protobuf: bool,
+}
+
+impl PatternOptions {
+    /// BLOB cells are matched as bytes or as protobuf messages
+    pub fn matches_blobs(&self) -> bool {
+        self.blobs || self.protobuf
+    }
+}
+
+pub enum Pattern {
+    Always(PatternOptions),
+    Fixed((FixedPattern, PatternOptions)),
+    Regex((RegexPattern, PatternOptions)),
+    /// Matches BLOB cells only
+    Binary((BinaryPattern, PatternOptions)),
+    Encoded((EncodedPattern, PatternOptions)),
+}
+
+/// Fixed string with a searcher prepared for it, values are matched without copying
+pub struct FixedPattern {
+    pattern: String,
+    searcher: Searcher,
+}
+
+enum Searcher {
+    Exact(Box<memmem::Finder<'static>>),
+    AsciiCaseless(AhoCorasick),
+    /// Non-ASCII pattern is folded with Unicode rules by an escaped regex
+    Caseless(regex::bytes::Regex),
 }
 
-pub(crate) enum Pattern {
-    Always,
-    Fixed((String, PatternOptions)),
-    Regex((regex::Regex, PatternOptions)),
+/// Regular expression compiled for text and for bytes which aren't valid UTF-8
+pub struct RegexPattern {
+    text: regex::Regex,
+    bytes: regex::bytes::Regex,
+}
+
+/// Pattern for raw bytes of BLOB cells
+pub struct BinaryPattern {
+    matcher: BinaryMatcher,
+}
+
+enum BinaryMatcher {
+    Hex(Box<memmem::Finder<'static>>),
+    Regex(regex::bytes::Regex),
 }
 
 impl Pattern {
@@ -24,41 +88,80 @@ impl Pattern {
         match kind {
             PatternKind::Regex => {
                 if pattern.is_empty() || pattern == ".*" {
-                    Ok(Self::Always)
+                    Ok(Self::Always(options))
                 } else {
-                    regex::RegexBuilder::new(pattern)
-                        .case_insensitive(options.case_insensitive)
-                        .build()
-                        .map(|value| Self::Regex((value, options)))
-                        .map_err(SQLError::Regex)
+                    RegexPattern::new(pattern, &options).map(|value| Self::Regex((value, options)))
                 }
             }
             PatternKind::Fixed => Ok(if pattern.is_empty() {
-                Self::Always
+                Self::Always(options)
             } else {
-                let pattern = if options.case_insensitive {
-                    pattern.to_lowercase()
-                } else {
-                    pattern.to_owned()
-                };
+                let pattern = FixedPattern::new(pattern, &options)?;
                 Self::Fixed((pattern, options))
             }),
+            PatternKind::Hex | PatternKind::ByteRegex => {
+                let pattern = BinaryPattern::new(pattern, kind, &options)?;
+                let options = PatternOptions {
+                    blobs: true,
+                    ..options
+                };
+                Ok(Self::Binary((pattern, options)))
+            }
+            PatternKind::Encoded(encodings) => {
+                let pattern = EncodedPattern::new(
+                    pattern,
+                    encodings,
+                    options.case_insensitive,
+                    options.whole_string,
+                )?;
+                let options = PatternOptions {
+                    blobs: true,
+                    ..options
+                };
+                Ok(Self::Encoded((pattern, options)))
+            }
+        }
+    }
+
+    pub fn options(&self) -> &PatternOptions {
+        match self {
+            Pattern::Always(options)
+            | Pattern::Fixed((_, options))
+            | Pattern::Regex((_, options))
+            | Pattern::Binary((_, options))
+            | Pattern::Encoded((_, options)) => options,
         }
     }
 
     pub fn is_match(&self, value: &str) -> bool {
         match self {
-            Pattern::Always => true,
-            Pattern::Fixed((pattern, options)) => {
-                match (options.case_insensitive, options.whole_string) {
-                    (true, true) => value.to_lowercase() == *pattern,
-                    (false, true) => value == pattern,
-                    (true, false) => value.to_lowercase().contains(pattern),
-                    (false, false) => value.contains(pattern),
+            Pattern::Always(_) => true,
+            Pattern::Fixed((pattern, options)) => pattern.is_match(value, options.whole_string),
+            Pattern::Regex((pattern, options)) => {
+                let Some(pattern_match) = pattern.text.find(value) else {
+                    return false;
+                };
+
+                if options.whole_string {
+                    pattern_match.len() == value.len()
+                } else {
+                    true
                 }
             }
+            Pattern::Binary(_) => false,
+            Pattern::Encoded(_) => self.find_encoded(value.as_bytes()).is_some(),
+        }
+    }
+
+    /// Match text which isn't valid UTF-8 or BLOB data
+    pub fn is_match_bytes(&self, value: &[u8]) -> bool {
+        match self {
+            Pattern::Always(_) => true,
+            Pattern::Fixed((pattern, options)) => {
+                pattern.is_match_bytes(value, options.whole_string)
+            }
             Pattern::Regex((pattern, options)) => {
-                let Some(pattern_match) = pattern.find(value) else {
+                let Some(pattern_match) = pattern.bytes.find(value) else {
                     return false;
                 };
 
@@ -68,6 +171,218 @@ impl Pattern {
                     true
                 }
             }
+            Pattern::Binary(_) => false,
+            Pattern::Encoded(_) => self.find_encoded(value).is_some(),
+        }
+    }
+
+    /// Range and encoding of the first match for encoded patterns
+    pub(crate) fn find_encoded(&self, value: &[u8]) -> Option<(Range<usize>, Encoding)> {
+        let Pattern::Encoded((pattern, options)) = self else {
+            return None;
+        };
+
+        pattern.find(value, options.whole_string)
+    }
+
+    /// Range of the first match in BLOB data for binary patterns
+    pub(crate) fn find_binary(&self, value: &[u8]) -> Option<Range<usize>> {
+        let Pattern::Binary((pattern, options)) = self else {
+            return None;
+        };
+
+        let range = match &pattern.matcher {
+            BinaryMatcher::Hex(finder) => {
+                let start = finder.find(value)?;
+                start..start + finder.needle().len()
+            }
+            BinaryMatcher::Regex(regex) => regex.find(value)?.range(),
+        };
+
+        if options.whole_string && range.len() != value.len() {
+            None
+        } else {
+            Some(range)
+        }
+    }
+}
+
+impl BinaryPattern {
+    fn new(pattern: &str, kind: &PatternKind, options: &PatternOptions) -> Result<Self, SQLError> {
+        if let PatternKind::Hex = kind {
+            let bytes = parse_hex(pattern)
+                .map_err(|error| SQLError::Configuration(format!("hex pattern: {error}")))?;
+            let finder = memmem::Finder::new(&bytes).into_owned();
+            return Ok(Self {
+                matcher: BinaryMatcher::Hex(Box::new(finder)),
+            });
+        }
+
+        regex::bytes::RegexBuilder::new(pattern)
+            .unicode(false)
+            .case_insensitive(options.case_insensitive)
+            .build()
+            .map(|regex| Self {
+                matcher: BinaryMatcher::Regex(regex),
+            })
+            .map_err(SQLError::Regex)
+    }
+
+    /// Bytes to find for hex pattern
+    pub fn hex_bytes(&self) -> Option<&[u8]> {
+        match &self.matcher {
+            BinaryMatcher::Hex(finder) => Some(finder.needle()),
+            BinaryMatcher::Regex(_) => None,
+        }
+    }
+}
+
+/// Parse hex bytes with optional `0x` prefix, whitespace between bytes is ignored
+fn parse_hex(pattern: &str) -> Result<Vec<u8>, String> {
+    let pattern = pattern.strip_prefix("0x").unwrap_or(pattern);
+    let digits = pattern
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace())
+        .map(|c| {
+            c.to_digit(16)
+                .map(|digit| digit as u8)
+                .ok_or_else(|| format!("invalid hex digit '{c}'"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if digits.is_empty() {
+        return Err("no bytes to find".into());
+    }
+    if !digits.len().is_multiple_of(2) {
+        return Err("odd number of hex digits".into());
+    }
+
+    Ok(digits
+        .chunks_exact(2)
+        .map(|pair| (pair[0] << 4) | pair[1])
+        .collect())
+}
+
+impl FixedPattern {
+    fn new(pattern: &str, options: &PatternOptions) -> Result<Self, SQLError> {
+        let searcher = if !options.case_insensitive {
+            Searcher::Exact(Box::new(memmem::Finder::new(pattern).into_owned()))
+        } else if pattern.is_ascii() {
+            AhoCorasick::builder()
+                .ascii_case_insensitive(true)
+                .build([pattern])
+                .map(Searcher::AsciiCaseless)
+                .map_err(|error| SQLError::Configuration(format!("pattern: {error}")))?
+        } else {
+            let escaped = regex::escape(pattern);
+            let escaped = if options.whole_string {
+                format!("^(?:{escaped})$")
+            } else {
+                escaped
+            };
+            regex::bytes::RegexBuilder::new(&escaped)
+                .case_insensitive(true)
+                .build()
+                .map(Searcher::Caseless)
+                .map_err(SQLError::Regex)?
+        };
+
+        Ok(Self {
+            pattern: pattern.to_owned(),
+            searcher,
+        })
+    }
+
+    /// Pattern as given by user
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    fn is_match(&self, value: &str, whole_string: bool) -> bool {
+        self.is_match_bytes(value.as_bytes(), whole_string)
+    }
+
+    fn is_match_bytes(&self, value: &[u8], whole_string: bool) -> bool {
+        match (&self.searcher, whole_string) {
+            (Searcher::Exact(_), true) => value == self.pattern.as_bytes(),
+            (Searcher::Exact(finder), false) => finder.find(value).is_some(),
+            (Searcher::AsciiCaseless(_), true) => {
+                value.eq_ignore_ascii_case(self.pattern.as_bytes())
+            }
+            (Searcher::AsciiCaseless(searcher), false) => searcher.is_match(value),
+            (Searcher::Caseless(regex), _) => regex.is_match(value),
         }
     }
 }
+
+impl RegexPattern {
+    fn new(pattern: &str, options: &PatternOptions) -> Result<Self, SQLError> {
+        let text = regex::RegexBuilder::new(pattern)
+            .case_insensitive(options.case_insensitive)
+            .build()
+            .map_err(SQLError::Regex)?;
+        let bytes = regex::bytes::RegexBuilder::new(pattern)
+            .case_insensitive(options.case_insensitive)
+            .build()
+            .map_err(SQLError::Regex)?;
+
+        Ok(Self { text, bytes })
+    }
+
+    /// Regular expression as given by user
+    pub fn as_str(&self) -> &str {
+        self.text.as_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixed(pattern: &str, case_insensitive: bool, whole_string: bool) -> Pattern {
+        let options = PatternOptions {
+            case_insensitive,
+            whole_string,
+            ..PatternOptions::default()
+        };
+        Pattern::new(pattern, &PatternKind::Fixed, options).unwrap()
+    }
+
+    #[test]
+    fn fixed_pattern_is_exact() {
+        let pattern = fixed("needle", false, false);
+        assert!(pattern.is_match("haystack with needle"));
+        assert!(!pattern.is_match("NEEDLE"));
+        assert!(pattern.is_match_bytes(b"\xffneedle\xfe"));
+
+        let pattern = fixed("needle", false, true);
+        assert!(pattern.is_match("needle"));
+        assert!(!pattern.is_match("needles"));
+    }
+
+    #[test]
+    fn ascii_pattern_is_caseless() {
+        let pattern = fixed("NeedLe", true, false);
+        assert!(pattern.is_match("a needle"));
+        assert!(pattern.is_match_bytes(b"\xffNEEDLE"));
+        assert!(!pattern.is_match("needl"));
+
+        let pattern = fixed("NeedLe", true, true);
+        assert!(pattern.is_match("NEEDLE"));
+        assert!(!pattern.is_match("a needle"));
+    }
+
+    #[test]
+    fn unicode_pattern_is_folded() {
+        let pattern = fixed("Straße", true, false);
+        assert!(pattern.is_match("die STRAßE"));
+        assert!(pattern.is_match_bytes("\u{1E9E} straße".as_bytes()));
+        assert!(!pattern.is_match("strasse"));
+
+        // Regex special characters are escaped
+        let pattern = fixed("Ä.*", true, true);
+        assert!(pattern.is_match("ä.*"));
+        assert!(!pattern.is_match("äbc"));
+        assert!(!pattern.is_match("xä.*"));
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::error::Level;
+
+/// Progress of sources being scanned at the moment
+#[derive(Default)]
+pub(crate) struct Progress {
+    sources: Mutex<Vec<Arc<SourceProgress>>>,
+}
+
+/// Counters of a single source
+pub(crate) struct SourceProgress {
+    name: String,
+    /// Estimated number of rows, if known
+    total: Option<u64>,
+    started: Instant,
+    pub rows: AtomicU64,
+    pub matches: AtomicU64,
+}
+
+impl Progress {
+    /// Register source to report progress for
+    pub fn start(&self, name: &str, total: Option<u64>) -> Arc<SourceProgress> {
+        let source = Arc::new(SourceProgress {
+            name: name.to_owned(),
+            total,
+            started: Instant::now(),
+            rows: AtomicU64::new(0),
+            matches: AtomicU64::new(0),
+        });
+
+        if let Ok(mut sources) = self.sources.lock() {
+            sources.push(source.clone());
+        }
+
+        source
+    }
+
+    /// Stop reporting progress for the source
+    pub fn finish(&self, source: &Arc<SourceProgress>) {
+        if let Ok(mut sources) = self.sources.lock() {
+            sources.retain(|value| !Arc::ptr_eq(value, source));
+        }
+    }
+
+    /// Periodically report status of active sources until the future is dropped
+    pub async fn report(self: Arc<Self>, period: Duration) {
+        let mut interval = tokio::time::interval(period);
+        // first tick completes immediately
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+
+            let Ok(sources) = self.sources.lock() else {
+                return;
+            };
+
+            sources.iter().for_each(|source| source.report());
+        }
+    }
+}
+
+impl SourceProgress {
+    fn report(&self) {
+        let rows = self.rows.load(Ordering::Relaxed);
+        let matches = self.matches.load(Ordering::Relaxed);
+        let elapsed = self.started.elapsed().as_secs_f64();
+
+        let rows_status = match self.total {
+            Some(total) if total > 0 => {
+                let percent = rows as f64 * 100.0 / total as f64;
+                format!("{rows}/{total} rows ({percent:.1}%)")
+            }
+            _ => format!("{rows} rows"),
+        };
+
+        let throughput = if elapsed > 0.0 {
+            rows as f64 / elapsed
+        } else {
+            0.0
+        };
+
+        log::log!(
+            Level::Info,
+            "{}: {rows_status}, {matches} matches, {throughput:.0} rows/s",
+            self.name
+        );
+    }
+}
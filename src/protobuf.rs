@@ -0,0 +1,219 @@
+use std::borrow::Cow;
+
+use crate::cell_to_string::{Cell, CellMatch};
+use crate::pattern::Pattern;
+
+/// Messages nested deeper aren't parsed
+const MAX_DEPTH: usize = 32;
+
+/// Field of a message in protobuf wire format
+struct Field<'a> {
+    number: u64,
+    value: FieldValue<'a>,
+}
+
+enum FieldValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Fixed32(u32),
+    /// String, bytes, nested message or packed repeated field
+    Bytes(&'a [u8]),
+}
+
+/// Parse a message without schema, `None` when data isn't a valid non-empty message.
+///
+/// Deprecated groups aren't supported.
+fn parse_message(mut data: &[u8]) -> Option<Vec<Field<'_>>> {
+    let mut fields = vec![];
+    while !data.is_empty() {
+        let key = read_varint(&mut data)?;
+        let number = key >> 3;
+        if number == 0 || number > 0x1fff_ffff {
+            return None;
+        }
+
+        let value = match key & 7 {
+            0 => FieldValue::Varint(read_varint(&mut data)?),
+            1 => {
+                let (bytes, rest) = data.split_first_chunk::<8>()?;
+                data = rest;
+                FieldValue::Fixed64(u64::from_le_bytes(*bytes))
+            }
+            2 => {
+                let length = usize::try_from(read_varint(&mut data)?).ok()?;
+                if length > data.len() {
+                    return None;
+                }
+                let (bytes, rest) = data.split_at(length);
+                data = rest;
+                FieldValue::Bytes(bytes)
+            }
+            5 => {
+                let (bytes, rest) = data.split_first_chunk::<4>()?;
+                data = rest;
+                FieldValue::Fixed32(u32::from_le_bytes(*bytes))
+            }
+            _ => return None,
+        };
+        fields.push(Field { number, value });
+    }
+
+    (!fields.is_empty()).then_some(fields)
+}
+
+fn read_varint(data: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for (index, byte) in data.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7f) << (7 * index);
+        if byte & 0x80 == 0 {
+            *data = &data[index + 1..];
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Match fields of a BLOB parsed as protobuf message, `None` when it isn't a message.
+///
+/// Numbers are matched as decimal. Length-delimited fields are matched as text when they are
+/// printable UTF-8 and as nested messages when they can be parsed, as bytes otherwise.
+/// The first match is returned with path of field numbers, e.g. `3.1.2`.
+pub(crate) fn find_in_protobuf(value: &[u8], pattern: &Pattern) -> Option<Option<CellMatch>> {
+    let fields = parse_message(value)?;
+    let mut path = vec![];
+    Some(find_in_fields(&fields, pattern, &mut path))
+}
+
+fn find_in_fields(fields: &[Field], pattern: &Pattern, path: &mut Vec<u64>) -> Option<CellMatch> {
+    for field in fields {
+        path.push(field.number);
+        let found = find_in_field(&field.value, pattern, path);
+        path.pop();
+        if found.is_some() {
+            return found;
+        }
+    }
+    None
+}
+
+fn find_in_field(value: &FieldValue, pattern: &Pattern, path: &mut Vec<u64>) -> Option<CellMatch> {
+    let number = match value {
+        FieldValue::Varint(value) | FieldValue::Fixed64(value) => value.to_string(),
+        FieldValue::Fixed32(value) => value.to_string(),
+        FieldValue::Bytes(bytes) => {
+            // Text without control characters is taken as string even if it parses as message
+            let text = std::str::from_utf8(bytes).ok().filter(|text| {
+                !text
+                    .chars()
+                    .any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r'))
+            });
+            if let Some(text) = text {
+                if let Some(found) = found_at(Cell::Value(Cow::Borrowed(text)), pattern, path) {
+                    return Some(found);
+                }
+            }
+
+            if path.len() < MAX_DEPTH {
+                if let Some(fields) = parse_message(bytes) {
+                    return find_in_fields(&fields, pattern, path);
+                }
+            }
+
+            if text.is_some() {
+                return None;
+            }
+
+            // Binary patterns match BLOB only, offset in the field isn't the one in the cell
+            let bytes = Cow::Borrowed(*bytes);
+            let cell = match pattern {
+                Pattern::Binary(_) => Cell::Blob(bytes),
+                _ => Cell::Bytes(bytes),
+            };
+            return found_at(cell, pattern, path).map(|found| CellMatch {
+                offset: None,
+                ..found
+            });
+        }
+    };
+
+    found_at(Cell::Value(Cow::Owned(number)), pattern, path)
+}
+
+fn found_at(cell: Cell<'_>, pattern: &Pattern, path: &[u64]) -> Option<CellMatch> {
+    let mut found = cell.find_value(pattern)?;
+    found.protobuf_path = Some(
+        path.iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("."),
+    );
+    Some(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::{PatternKind, PatternOptions};
+
+    fn message() -> Vec<u8> {
+        let nested = [&[0x0a, 11][..], b"deep needle"].concat();
+        [
+            // 1: varint 150
+            &[0x08, 0x96, 0x01][..],
+            // 2: string
+            &[0x12, 6],
+            b"needle",
+            // 3: nested message
+            &[0x1a, nested.len() as u8],
+            &nested,
+            // 4: fixed32 7, 5: fixed64 42
+            &[0x25, 7, 0, 0, 0],
+            &[0x29, 42, 0, 0, 0, 0, 0, 0, 0],
+            // 6: bytes
+            &[0x32, 3, 0xde, 0xad, 0xff],
+        ]
+        .concat()
+    }
+
+    fn found_path(pattern: &str, kind: PatternKind) -> Option<String> {
+        let pattern = Pattern::new(pattern, &kind, PatternOptions::default()).unwrap();
+        find_in_protobuf(&message(), &pattern)
+            .unwrap()
+            .map(|found| found.protobuf_path.unwrap())
+    }
+
+    #[test]
+    fn fields_are_walked_in_order() {
+        assert_eq!(
+            found_path("needle", PatternKind::Fixed).as_deref(),
+            Some("2")
+        );
+        assert_eq!(
+            found_path("deep", PatternKind::Fixed).as_deref(),
+            Some("3.1")
+        );
+        assert_eq!(found_path("150", PatternKind::Fixed).as_deref(), Some("1"));
+        assert_eq!(found_path("^7$", PatternKind::Regex).as_deref(), Some("4"));
+        assert_eq!(found_path("42", PatternKind::Fixed).as_deref(), Some("5"));
+        assert_eq!(found_path("missing", PatternKind::Fixed), None);
+    }
+
+    #[test]
+    fn binary_fields_are_matched_as_bytes() {
+        assert_eq!(found_path("ADFF", PatternKind::Hex).as_deref(), Some("6"));
+        assert_eq!(found_path("DEAD", PatternKind::Hex).as_deref(), Some("6"));
+    }
+
+    #[test]
+    fn other_blobs_arent_messages() {
+        let pattern =
+            Pattern::new("needle", &PatternKind::Fixed, PatternOptions::default()).unwrap();
+
+        assert!(find_in_protobuf(b"needle", &pattern).is_none());
+        assert!(find_in_protobuf(b"", &pattern).is_none());
+        // Field length beyond the end of data
+        assert!(find_in_protobuf(&[0x12, 7, b'n'], &pattern).is_none());
+        // Field number 0
+        assert!(find_in_protobuf(&[0x00, 1], &pattern).is_none());
+    }
+}
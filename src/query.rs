@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+
+use sqlparser::ast::Expr;
 use sqlparser::dialect::Dialect;
 
 use crate::error::SQLError;
@@ -6,24 +9,33 @@ use crate::select::{escape_table_name, generate_select, read_verify_query};
 #[non_exhaustive]
 pub(crate) enum SelectVariant {
     WholeDB,
-    Queries(Vec<(String, String)>),
+    Queries(Vec<Source>),
+}
+
+/// Query to scan
+pub(crate) struct Source {
+    /// Identifier in output, e.g. ``Table `name` `` or `Query #1`
+    pub id: String,
+    pub query: String,
+    /// Table name if query selects the whole table
+    pub table: Option<String>,
 }
 
 pub(crate) fn prepare_queries<T>(
     table: T,
     queries: T,
+    filters: &HashMap<String, Expr>,
     dialect: &impl Dialect,
     ignore_non_read: bool,
 ) -> Result<SelectVariant, SQLError>
 where
     T: Iterator<Item = String>,
 {
-    let mut queries_result: Vec<(String, String)> = table
-        .map(|table_name| {
-            (
-                format!("Table {}", escape_table_name(table_name.as_str(), dialect)),
-                generate_select(&table_name, dialect),
-            )
+    let mut queries_result: Vec<Source> = table
+        .map(|table_name| Source {
+            id: format!("Table {}", escape_table_name(table_name.as_str(), dialect)),
+            query: generate_select(&table_name, filters.get(&table_name).cloned(), dialect),
+            table: Some(table_name),
         })
         .collect();
 
@@ -31,7 +43,13 @@ where
     queries.into_iter().try_fold((), |(), sql| {
         read_verify_query(&sql, dialect, ignore_non_read, &mut idx)?
             .into_iter()
-            .for_each(|query| queries_result.push((format!("Query #{idx}"), query)));
+            .for_each(|query| {
+                queries_result.push(Source {
+                    id: format!("Query #{idx}"),
+                    query,
+                    table: None,
+                });
+            });
         Ok(())
     })?;
 
@@ -0,0 +1,329 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use memmap2::Mmap;
+use sqlx::{Executor as _, Pool, Row as _, Sqlite};
+
+use crate::carve::FileSource;
+use crate::cell_to_string::{sqlite_cell_to_string, Cell};
+use crate::error::{Level, SQLError};
+use crate::limits::Limits;
+use crate::pattern::Pattern;
+use crate::progress::SourceProgress;
+use crate::search::Event;
+use crate::sqlite_file::{
+    decode_text, header_offset, BtreePage, DatabaseFile, PageKind, RecordValue, RecordValues,
+};
+use crate::stats::SourceStats;
+
+/// Table which can be read from the database file without SQL
+pub(crate) struct RawTable {
+    root: u32,
+    /// Columns returned by `SELECT *`, in order they are stored in records
+    columns: Vec<String>,
+    /// `INTEGER PRIMARY KEY` column, its value is rowid
+    rowid_column: Option<usize>,
+    /// Values of columns added after older records were written
+    defaults: Vec<Cell<'static>>,
+}
+
+/// Map the database file and find tables which can be read from it directly.
+///
+/// Tables which aren't returned are queried with SQL.
+pub(crate) async fn sqlite_raw_tables(
+    db: &Pool<Sqlite>,
+    path: &Path,
+    tables: impl Iterator<Item = &str>,
+) -> Option<(Arc<Mmap>, HashMap<String, Arc<RawTable>>)> {
+    let map = match map_file(path) {
+        Ok(map) => map,
+        Err(error) => {
+            error.report(Level::Warn);
+            return None;
+        }
+    };
+
+    let file = match DatabaseFile::new(&map) {
+        Ok(file) => file,
+        Err(error) => {
+            log::warn!("Can't read \"{}\" directly: {error}", path.display());
+            return None;
+        }
+    };
+
+    let mut raw_tables = HashMap::new();
+    for table in tables {
+        match sqlite_raw_table(db, &file, table).await {
+            Ok(raw_table) => {
+                raw_tables.insert(table.to_owned(), Arc::new(raw_table));
+            }
+            Err(reason) => log::debug!("{table}: {reason}, table is queried"),
+        }
+    }
+
+    Some((Arc::new(map), raw_tables))
+}
+
+pub(crate) fn map_file(path: &Path) -> Result<Mmap, SQLError> {
+    let context = || format!("map \"{}\"", path.display());
+    let file = File::open(path).map_err(|error| SQLError::Io((context(), error)))?;
+
+    // SAFETY: mapped file must not be changed while it's read. Callers map only databases opened
+    // as immutable, which SQLite assumes to stay the same as well. Databases which can be written
+    // to are opened with `--no-immutable` or `--include-wal` and aren't mapped.
+    unsafe { Mmap::map(&file) }.map_err(|error| SQLError::Io((context(), error)))
+}
+
+/// Check that rows of the table are stored as they are returned by `SELECT *`
+async fn sqlite_raw_table(
+    db: &Pool<Sqlite>,
+    file: &DatabaseFile<'_>,
+    table: &str,
+) -> Result<RawTable, String> {
+    let root_query = "SELECT rootpage FROM sqlite_schema WHERE type = 'table' AND name = ?";
+
+    log::debug!("Execute query: {root_query} with {table}");
+
+    let root = db
+        .fetch_optional(sqlx::query(root_query).bind(table))
+        .await
+        .map_err(|error| error.to_string())?
+        .and_then(|row| row.try_get::<i64, usize>(0).ok())
+        .and_then(|root| u32::try_from(root).ok())
+        .filter(|&root| root > 0)
+        .ok_or("not stored in the database file")?;
+
+    // Tables without rowid are stored in index b-trees
+    let kind = file
+        .page(root)
+        .and_then(|page| BtreePage::parse(page, header_offset(root)))
+        .map(|btree| btree.kind);
+    if !matches!(kind, Some(PageKind::LeafTable | PageKind::InteriorTable)) {
+        return Err("root page isn't a table b-tree".into());
+    }
+
+    let columns_query = "SELECT name, type, pk, hidden, dflt_value FROM pragma_table_xinfo(?)";
+
+    log::debug!("Execute query: {columns_query} with {table}");
+
+    let rows = db
+        .fetch_all(sqlx::query(columns_query).bind(table))
+        .await
+        .map_err(|error| error.to_string())?;
+
+    let mut columns = vec![];
+    let mut keys = vec![];
+    let mut defaults = vec![];
+    for row in rows {
+        let column = |index| row.try_get::<Option<String>, usize>(index);
+        let number = |index| row.try_get::<i64, usize>(index);
+        let (name, declared, pk, hidden, default) =
+            match (column(0), column(1), number(2), number(3), column(4)) {
+                (Ok(name), Ok(declared), Ok(pk), Ok(hidden), Ok(default)) => {
+                    (name, declared, pk, hidden, default)
+                }
+                _ => return Err("can't read columns".into()),
+            };
+
+        // Generated columns aren't stored or stored in a different order
+        if hidden != 0 {
+            return Err("has hidden or generated columns".into());
+        }
+
+        if pk > 0 {
+            keys.push((columns.len(), declared));
+        }
+        columns.push(name.unwrap_or_default());
+        defaults.push(match default {
+            Some(expression) => sqlite_default_value(db, &expression).await?,
+            None => Cell::Null,
+        });
+    }
+
+    let rowid_column = match keys.as_slice() {
+        [(index, Some(declared))] if declared.eq_ignore_ascii_case("INTEGER") => Some(*index),
+        _ => None,
+    };
+
+    Ok(RawTable {
+        root,
+        columns,
+        rowid_column,
+        defaults,
+    })
+}
+
+async fn sqlite_default_value(
+    db: &Pool<Sqlite>,
+    expression: &str,
+) -> Result<Cell<'static>, String> {
+    let default_query = format!("SELECT {expression}");
+
+    log::debug!("Execute query: {default_query}");
+
+    let row = db
+        .fetch_one(default_query.as_str())
+        .await
+        .map_err(|error| format!("can't evaluate default value: {error}"))?;
+    let value = row.try_get_raw(0).map_err(|error| error.to_string())?;
+
+    let mut buffer = String::new();
+    sqlite_cell_to_string(value, &mut buffer).map(Cell::into_owned)
+}
+
+/// Match cells of table rows read from leaf pages of its b-tree.
+///
+/// Rows and cells are the same as returned by `SELECT *`, text is matched without copying.
+pub(crate) fn scan_raw_table(
+    data: &[u8],
+    table: &RawTable,
+    id: String,
+    pattern: &Pattern,
+    progress: &SourceProgress,
+    limits: &Limits,
+    emit: &mut impl FnMut(Event),
+) -> SourceStats {
+    let mut source = FileSource::start(id.clone(), &table.columns, pattern, limits, emit);
+
+    let file = match DatabaseFile::new(data) {
+        Ok(file) => file,
+        Err(error) => {
+            emit(Event::Error(SQLError::Configuration(format!(
+                "{id}: {error}"
+            ))));
+            source.stats.failed = true;
+            return source.finish(emit).1;
+        }
+    };
+    let encoding = file.header.encoding;
+
+    // Numbers are formatted into the same buffer
+    let mut number = String::new();
+
+    'pages: for page_number in file.leaf_pages(table.root) {
+        let Some(page) = file.page(page_number) else {
+            continue;
+        };
+        let Some(btree) = BtreePage::parse(page, header_offset(page_number)) else {
+            continue;
+        };
+        if btree.kind != PageKind::LeafTable {
+            continue;
+        }
+
+        for offset in btree.cell_offsets(page) {
+            if !source.is_running(emit) {
+                break 'pages;
+            }
+            if limits.is_error_budget_exceeded(&source.stats) {
+                log::warn!("{id}: too many errors, skip the rest");
+                source.stats.failed = true;
+                break 'pages;
+            }
+
+            let row = source.stats.rows;
+            let malformed = || {
+                let error = std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("malformed cell at page {page_number}, offset {offset}"),
+                );
+                Event::Error(SQLError::Io((format!("{id}::{row}"), error)))
+            };
+
+            let Some(cell) = file.cell(page, btree.kind, offset, |overflow| file.page(overflow))
+            else {
+                emit(malformed());
+                source.stats.read_errors += 1;
+                continue;
+            };
+            let Some(mut values) = RecordValues::new(&cell.payload) else {
+                emit(malformed());
+                source.stats.read_errors += 1;
+                continue;
+            };
+            let rowid = cell.rowid.unwrap_or_default();
+            let matches = source.stats.matches;
+
+            // Columns added by `ALTER TABLE` are missing in older records
+            let mut stored = 0;
+            for index in 0..table.columns.len() {
+                let value = match values.next() {
+                    Some(value) => {
+                        stored += 1;
+                        value
+                    }
+                    None => RecordValue::Null,
+                };
+                source.stats.cells += 1;
+
+                let value = match value {
+                    RecordValue::Null if table.rowid_column == Some(index) => {
+                        number.clear();
+                        let _ = write!(number, "{rowid}");
+                        Cell::Value(Cow::Borrowed(&number))
+                    }
+                    RecordValue::Null if index >= stored => table.defaults[index].borrowed(),
+                    RecordValue::Null => Cell::Null,
+                    RecordValue::Integer(value) => {
+                        number.clear();
+                        let _ = write!(number, "{value}");
+                        Cell::Value(Cow::Borrowed(&number))
+                    }
+                    RecordValue::Real(value) => {
+                        number.clear();
+                        let _ = write!(number, "{value}");
+                        Cell::Value(Cow::Borrowed(&number))
+                    }
+                    RecordValue::Text(bytes) => match decode_text(bytes, encoding) {
+                        Some(text) => Cell::Value(text),
+                        None => Cell::Bytes(Cow::Borrowed(bytes)),
+                    },
+                    RecordValue::Blob(bytes) => Cell::Blob(Cow::Borrowed(bytes)),
+                };
+
+                match value {
+                    Cell::Null => {
+                        source.stats.skipped_null += 1;
+                        continue;
+                    }
+                    Cell::Blob(_) if !pattern.options().matches_blobs() => {
+                        source.stats.skipped_blob += 1;
+                        continue;
+                    }
+                    Cell::Unknown => {
+                        source.stats.skipped_unknown += 1;
+                        continue;
+                    }
+                    _ => {}
+                }
+
+                let Some(value) = value.find(pattern) else {
+                    continue;
+                };
+                if !source.found(row, index, value, None, Some(rowid), emit) {
+                    break 'pages;
+                }
+            }
+
+            // Values which don't fit into a record are taken for missing columns
+            let _ = values.by_ref().count();
+            if !values.is_complete() {
+                emit(malformed());
+                source.stats.read_errors += 1;
+            }
+
+            source.stats.rows += 1;
+            progress.rows.fetch_add(1, Ordering::Relaxed);
+            progress
+                .matches
+                .fetch_add(source.stats.matches - matches, Ordering::Relaxed);
+        }
+    }
+
+    source.finish(emit).1
+}
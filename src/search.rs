@@ -0,0 +1,1099 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures::channel::mpsc::{channel, Receiver, Sender};
+use futures::{SinkExt as _, Stream, StreamExt as _};
+use sqlparser::ast::Expr;
+use sqlparser::dialect::{Dialect, SQLiteDialect};
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::{Executor as _, Pool, Row as _, Sqlite};
+use tokio::task::JoinSet;
+
+use crate::carve::carve_database;
+use crate::connection::{sqlite_databases, ConnectionOptions, Load, SqliteDatabase};
+use crate::decode::Decoding;
+use crate::dump::load_dump;
+use crate::encodings::Encoding;
+use crate::error::{Level, SQLError};
+use crate::filter::{combine_filters, generate_filter, generate_sample};
+use crate::import::load_data_file;
+use crate::limits::Limits;
+use crate::matching::sqlite_check_rows;
+use crate::output::OutputSink;
+use crate::pattern::Pattern;
+use crate::progress::Progress;
+use crate::query::{prepare_queries, SelectVariant, Source};
+use crate::raw::{scan_raw_table, sqlite_raw_tables};
+use crate::sqlite_file::SchemaTree;
+use crate::stats::{SourceStats, Stats};
+use crate::wal::scan_wal_history;
+
+/// Events waiting for the receiver, sources wait when it's full
+const EVENT_BUFFER: usize = 1024;
+
+/// Options to tune a search
+#[derive(Clone, Debug)]
+pub struct SearchOptions {
+    /// Skip non-readonly SQL queries instead of failing
+    pub ignore_non_readonly: bool,
+    /// Filter table rows in SQL before matching
+    pub prefilter: bool,
+    /// Number of tables or queries to scan at the same time
+    pub jobs: u32,
+    /// Deliver matches grouped by table or query in order they are passed
+    pub sort: bool,
+    /// Periodically log progress of every table or query
+    pub progress: bool,
+    /// Maximum number of matches per table or query
+    pub max_count: Option<u64>,
+    /// Maximum number of matches in total
+    pub max_total_count: Option<u64>,
+    /// Maximum number of rows to fetch from every table or query
+    pub max_rows: Option<u64>,
+    /// Percent of table rows to scan
+    pub sample: Option<f64>,
+    /// Maximum time to scan a single table or query
+    pub timeout: Option<Duration>,
+    /// Maximum time to scan everything
+    pub total_timeout: Option<Duration>,
+    /// Maximum number of errors per table or query
+    pub max_errors: Option<u64>,
+    /// Recover deleted records from free space of the database file
+    pub carve: bool,
+    /// Search every version of pages in write-ahead log and rollback journal
+    pub wal_history: bool,
+    /// How tables are read
+    pub engine: Engine,
+    /// Options of database connection
+    pub connection: ConnectionOptions,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            ignore_non_readonly: false,
+            prefilter: false,
+            jobs: 1,
+            sort: false,
+            progress: false,
+            max_count: None,
+            max_total_count: None,
+            max_rows: None,
+            sample: None,
+            timeout: None,
+            total_timeout: None,
+            max_errors: Some(100),
+            carve: false,
+            wal_history: false,
+            engine: Engine::default(),
+            connection: ConnectionOptions::default(),
+        }
+    }
+}
+
+/// Way to read rows of tables
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Engine {
+    /// Run SQL queries
+    #[default]
+    Sqlx,
+    /// Read table b-trees from the database file, tables which can't be read are queried
+    Raw,
+}
+
+/// Cell which matches the pattern
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Match {
+    /// Table or query, e.g. ``Table `name` `` or `Query #1`
+    pub source: String,
+    /// Row index starting with `0`
+    pub row: u64,
+    pub column: String,
+    /// Cell value converted to a string, hexdump around the match for binary patterns
+    pub value: String,
+    /// Offset of the match in BLOB value for binary and encoded patterns
+    pub offset: Option<usize>,
+    /// Encoding the value is found in for encoded patterns
+    pub encoding: Option<Encoding>,
+    /// Decodings applied to the cell value in order, value is the decoded one
+    pub decoding: Vec<Decoding>,
+    /// Path of the matched key or value inside JSON document, e.g. `$.user.emails[2]`
+    pub json_path: Option<String>,
+    /// Field numbers of the matched field inside protobuf message, e.g. `3.1.2`
+    pub protobuf_path: Option<String>,
+    /// Place in the database file for values read from the file directly
+    pub location: Option<Location>,
+    /// Rowid of the table row for values read from the file directly
+    pub rowid: Option<i64>,
+}
+
+impl fmt::Display for Match {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}::{}::{}", self.source, self.row, self.column)?;
+        if let Some(json_path) = &self.json_path {
+            write!(f, "::{json_path}")?;
+        }
+        if let Some(protobuf_path) = &self.protobuf_path {
+            write!(f, "::{protobuf_path}")?;
+        }
+        if let Some(location) = &self.location {
+            write!(f, " ({location})")?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " at byte {offset}")?;
+        }
+        if let Some(encoding) = self.encoding {
+            write!(f, " as {encoding}")?;
+        }
+        if !self.decoding.is_empty() {
+            write!(f, " via {}", format_decoding(&self.decoding))?;
+        }
+        write!(f, " => {}", self.value)
+    }
+}
+
+/// Decodings joined as `base64 > json`
+pub(crate) fn format_decoding(decoding: &[Decoding]) -> String {
+    decoding
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(" > ")
+}
+
+/// Place of a record in the database file
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Location {
+    /// Page number starting with `1`
+    pub page: u32,
+    /// Offset of the record from the beginning of the page
+    pub offset: usize,
+    /// Write-ahead log frame or rollback journal record starting with `1`
+    pub frame: Option<u32>,
+    /// Write-ahead log transaction of the frame starting with `1`
+    pub commit: Option<u32>,
+}
+
+impl Location {
+    pub(crate) fn new(page: u32, offset: usize) -> Self {
+        Self {
+            page,
+            offset,
+            frame: None,
+            commit: None,
+        }
+    }
+
+    /// Page version stored in a frame
+    pub(crate) fn in_frame(mut self, frame: u32, commit: Option<u32>) -> Self {
+        self.frame = Some(frame);
+        self.commit = commit;
+        self
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(frame) = self.frame {
+            write!(f, "frame {frame}, ")?;
+        }
+        if let Some(commit) = self.commit {
+            write!(f, "commit {commit}, ")?;
+        }
+        write!(f, "page {}, offset {}", self.page, self.offset)
+    }
+}
+
+/// Event of a running search
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Event {
+    /// Table or query scan is started
+    SourceStart(String),
+    Match(Match),
+    /// Error which doesn't stop the search
+    Error(SQLError),
+    /// Table or query scan is finished
+    SourceEnd((String, SourceStats)),
+}
+
+/// Builder for [`Search`]
+///
+/// ```no_run
+/// # async fn run() -> Result<(), sqlgrep::SQLError> {
+/// use futures::StreamExt;
+/// use sqlgrep::{Event, Pattern, PatternKind, PatternOptions, Search};
+///
+/// let pattern = Pattern::new("needle", &PatternKind::Fixed, PatternOptions::default())?;
+/// let mut events = Search::builder()
+///     .database("sqlite://haystack.db")
+///     .pattern(pattern)
+///     .tables(["messages"])
+///     .build()?
+///     .run();
+///
+/// while let Some(event) = events.next().await {
+///     if let Event::Match(found) = event {
+///         println!("{found}");
+///     }
+/// }
+///
+/// let stats = events.finish().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct SearchBuilder {
+    database_uri: Option<String>,
+    pattern: Option<Pattern>,
+    tables: Vec<String>,
+    queries: Vec<String>,
+    options: SearchOptions,
+}
+
+impl SearchBuilder {
+    /// Database URI or SQLite file name
+    #[must_use]
+    pub fn database(mut self, database_uri: impl Into<String>) -> Self {
+        self.database_uri = Some(database_uri.into());
+        self
+    }
+
+    #[must_use]
+    pub fn pattern(mut self, pattern: Pattern) -> Self {
+        self.pattern = Some(pattern);
+        self
+    }
+
+    /// Tables or views to scan
+    #[must_use]
+    pub fn tables<T: Into<String>>(mut self, tables: impl IntoIterator<Item = T>) -> Self {
+        self.tables.extend(tables.into_iter().map(Into::into));
+        self
+    }
+
+    /// SQL queries to scan, every one may contain multiple `SELECT` statements
+    #[must_use]
+    pub fn queries<T: Into<String>>(mut self, queries: impl IntoIterator<Item = T>) -> Self {
+        self.queries.extend(queries.into_iter().map(Into::into));
+        self
+    }
+
+    #[must_use]
+    pub fn options(mut self, options: SearchOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn build(self) -> Result<Search, SQLError> {
+        let database_uri = self
+            .database_uri
+            .ok_or_else(|| SQLError::Configuration("database is required".into()))?;
+        let pattern = self
+            .pattern
+            .ok_or_else(|| SQLError::Configuration("pattern is required".into()))?;
+
+        if self.options.jobs == 0 {
+            return Err(SQLError::Configuration("jobs must be at least 1".into()));
+        }
+
+        Ok(Search {
+            database_uri,
+            pattern,
+            tables: self.tables,
+            queries: self.queries,
+            options: self.options,
+        })
+    }
+}
+
+/// Search of a pattern in every cell of a database.
+///
+/// Whole database is scanned when neither tables nor queries are set.
+pub struct Search {
+    database_uri: String,
+    pattern: Pattern,
+    tables: Vec<String>,
+    queries: Vec<String>,
+    options: SearchOptions,
+}
+
+impl Search {
+    pub fn builder() -> SearchBuilder {
+        SearchBuilder::default()
+    }
+
+    /// Start search in background.
+    ///
+    /// Must be called within Tokio runtime.
+    pub fn run(self) -> EventStream {
+        let (sender, events) = channel(EVENT_BUFFER);
+
+        let options = &self.options;
+        let mut limits = Limits::new(options.max_count, options.max_total_count, options.max_rows);
+        limits.timeout = options.timeout;
+        limits.total_deadline = options
+            .total_timeout
+            .map(|timeout| Instant::now() + timeout);
+        limits.max_errors = options.max_errors;
+        let limits = Arc::new(limits);
+
+        let task = tokio::spawn(process_sqlite_database(self, limits.clone(), sender));
+
+        EventStream {
+            events,
+            task,
+            limits,
+        }
+    }
+
+    /// Run search and pass every event to the sink.
+    ///
+    /// Search is stopped on the first sink error.
+    pub async fn run_with_sink(self, sink: &mut impl OutputSink) -> Result<Stats, SQLError> {
+        let mut events = self.run();
+
+        while let Some(event) = events.next().await {
+            sink.event(event)?;
+        }
+
+        let stats = events.finish().await?;
+        sink.summary(&stats)?;
+
+        Ok(stats)
+    }
+}
+
+/// Stream of events produced by [`Search::run`].
+///
+/// Search is aborted when the stream is dropped. Sources read in blocking threads
+/// can't be aborted, they stop before the next row.
+pub struct EventStream {
+    events: Receiver<Event>,
+    task: tokio::task::JoinHandle<Result<Stats, SQLError>>,
+    limits: Arc<Limits>,
+}
+
+impl EventStream {
+    /// Wait until search is finished and return statistics.
+    ///
+    /// Events not consumed yet are dropped.
+    pub async fn finish(mut self) -> Result<Stats, SQLError> {
+        // Sources wait while the channel is full, the channel ends when all of them are done
+        while self.events.next().await.is_some() {}
+
+        match (&mut self.task).await {
+            Ok(result) => result,
+            Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+            Err(error) => Err(SQLError::Io((
+                "search task".into(),
+                std::io::Error::other(error),
+            ))),
+        }
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.events.poll_next_unpin(cx)
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        self.limits.stop();
+        self.task.abort();
+    }
+}
+
+/// State shared by all databases of a search
+struct SearchState {
+    pattern: Arc<Pattern>,
+    tables: Vec<String>,
+    queries: Vec<String>,
+    options: SearchOptions,
+    limits: Arc<Limits>,
+    progress: Arc<Progress>,
+    sender: Sender<Event>,
+}
+
+async fn process_sqlite_database(
+    search: Search,
+    limits: Arc<Limits>,
+    sender: Sender<Event>,
+) -> Result<Stats, SQLError> {
+    let started = Instant::now();
+    let Search {
+        database_uri,
+        pattern,
+        tables,
+        queries,
+        options,
+    } = search;
+
+    // Archives are extracted and WAL snapshots are copied with blocking IO
+    let connection = options.connection.clone();
+    let databases =
+        tokio::task::spawn_blocking(move || sqlite_databases(&database_uri, connection))
+            .await
+            .map_err(|error| {
+                SQLError::Io(("open database".into(), std::io::Error::other(error)))
+            })??;
+
+    let state = SearchState {
+        pattern: Arc::new(pattern),
+        tables,
+        queries,
+        options,
+        limits,
+        progress: Arc::new(Progress::default()),
+        sender,
+    };
+
+    // Reporter is aborted with the search
+    let mut reporter = JoinSet::new();
+    if state.options.progress {
+        reporter.spawn(state.progress.clone().report(Duration::from_secs(1)));
+    }
+
+    let mut stats = Stats::default();
+    let mut result = Ok(());
+    for database in databases {
+        let name = database.name.clone();
+
+        match (scan_database(&state, database).await, name) {
+            (Ok(sources), _) => stats.sources.extend(sources),
+            // Other extracted databases are still scanned
+            (Err(error), Some(name)) => {
+                let error = match error {
+                    SQLError::SqlX((context, error)) => {
+                        SQLError::SqlX((format!("{name}, {context}"), error))
+                    }
+                    error => error,
+                };
+                send(&state.sender, Event::Error(error)).await;
+                let failed = SourceStats {
+                    failed: true,
+                    ..SourceStats::default()
+                };
+                stats.sources.push((name, failed));
+            }
+            (Err(error), None) => {
+                result = Err(error);
+                break;
+            }
+        }
+    }
+
+    reporter.abort_all();
+
+    result?;
+
+    stats.elapsed = started.elapsed();
+    Ok(stats)
+}
+
+/// Scan all tables or queries of a single database
+async fn scan_database(
+    state: &SearchState,
+    database: SqliteDatabase,
+) -> Result<Vec<(String, SourceStats)>, SQLError> {
+    let dialect = SQLiteDialect {};
+    let options = &state.options;
+
+    // Temporary files must outlive the pool
+    let SqliteDatabase {
+        name,
+        connect_options,
+        temp_dir: _temp_dir,
+        encrypted,
+        immutable,
+        load,
+    } = database;
+
+    let context = |action: &str| match &name {
+        Some(name) => format!("{action} {name}"),
+        None => action.to_owned(),
+    };
+
+    let mut pool_options = SqlitePoolOptions::new().max_connections(options.jobs);
+    if let Some(timeout) = options.timeout {
+        pool_options = pool_options.acquire_timeout(timeout);
+    }
+
+    // Connection keeps in-memory database alive until the scan is finished
+    let _memory_connection = match &load {
+        Some(Load::Dump(path)) => Some(load_dump(path, &connect_options).await?),
+        Some(Load::Data(path, format)) => {
+            Some(load_data_file(path, *format, &connect_options).await?)
+        }
+        None => None,
+    };
+
+    let path = connect_options.get_filename().to_owned();
+    let db = pool_options
+        .connect_with(connect_options)
+        .await
+        .map_err(|error| SQLError::SqlX((context("Database connection"), error)))?;
+
+    // SQLCipher reports wrong key only when the database is read for the first time
+    if encrypted {
+        db.execute("SELECT count(*) FROM sqlite_schema")
+            .await
+            .map_err(|error| {
+                SQLError::SqlX((
+                    context("Database key, check the key and cipher settings"),
+                    error,
+                ))
+            })?;
+    }
+
+    let (pattern, tables) = (&state.pattern, &state.tables);
+    let (prefilter, sample) = (options.prefilter, options.sample);
+    let filters = sqlite_table_filters(&db, tables, pattern, prefilter, sample, &dialect).await?;
+
+    let select_variant = prepare_queries(
+        tables.iter().cloned(),
+        state.queries.iter().cloned(),
+        &filters,
+        &dialect,
+        options.ignore_non_readonly,
+    )?;
+
+    let mut queries = match select_variant {
+        SelectVariant::Queries(queries) => queries,
+        SelectVariant::WholeDB => {
+            let tables = sqlite_select_tables(&db).await?;
+            let filters =
+                sqlite_table_filters(&db, &tables, pattern, prefilter, sample, &dialect).await?;
+            let select_variant = prepare_queries(
+                tables.into_iter(),
+                vec![].into_iter(),
+                &filters,
+                &dialect,
+                options.ignore_non_readonly,
+            )?;
+            match select_variant {
+                SelectVariant::WholeDB => vec![],
+                SelectVariant::Queries(queries) => queries,
+            }
+        }
+    };
+
+    // Sources of extracted databases are told apart by archive path and member name
+    if let Some(name) = &name {
+        for source in &mut queries {
+            source.id = format!("{name}::{}", source.id);
+        }
+    }
+
+    // Tables read from the database file directly, other sources are queried
+    let mut raw = None;
+    if options.engine == Engine::Raw {
+        let reason = if load.is_some() {
+            Some("database is loaded into memory")
+        } else if encrypted {
+            Some("database is encrypted")
+        } else if prefilter || sample.is_some() {
+            Some("rows are filtered with SQL")
+        } else if !immutable {
+            // It's a private copy with `include_wal`, but the log is applied by SQLite only
+            Some("database isn't opened as immutable, its write-ahead log isn't in the file")
+        } else {
+            None
+        };
+        match reason {
+            Some(reason) => log::warn!("{}: {reason}", context("Query tables of")),
+            None => {
+                let tables = queries.iter().filter_map(|source| source.table.as_deref());
+                raw = sqlite_raw_tables(&db, &path, tables).await;
+            }
+        }
+    }
+
+    let jobs = options.jobs as usize;
+    let sort = options.sort;
+    // Number of rows passing the prefilter isn't known in advance
+    let show_total = options.progress && !prefilter;
+    let max_rows = options.max_rows;
+    let sender = &state.sender;
+
+    // Every source is a separate task to convert and match cells in parallel
+    let source_task = |source: Source| {
+        let (db, pattern, sender) = (db.clone(), pattern.clone(), sender.clone());
+        let (progress, limits) = (state.progress.clone(), state.limits.clone());
+        let raw_table = raw.as_ref().and_then(|(map, tables)| {
+            let table = tables.get(source.table.as_ref()?)?;
+            Some((map.clone(), table.clone()))
+        });
+        async move {
+            let total = match (&source.table, show_total) {
+                (Some(table), true) => sqlite_count_rows(&db, table).await.map(|total| {
+                    let total = match sample {
+                        Some(percent) => (total as f64 * percent / 100.0).ceil() as u64,
+                        None => total,
+                    };
+                    max_rows.map_or(total, |max_rows| total.min(max_rows))
+                }),
+                _ => None,
+            };
+            let source_progress = progress.start(&source.id, total);
+
+            let (stats, events) = match raw_table {
+                Some((map, table)) => {
+                    let (id, source_progress) = (source.id.clone(), source_progress.clone());
+                    tokio::task::spawn_blocking(move || {
+                        let mut emitter = Emitter::new(sender, sort);
+                        let stats = scan_raw_table(
+                            &map,
+                            &table,
+                            id,
+                            &pattern,
+                            &source_progress,
+                            &limits,
+                            &mut |event| emitter.emit_blocking(event),
+                        );
+                        (stats, emitter.into_events())
+                    })
+                    .await
+                    .unwrap_or_else(|error| std::panic::resume_unwind(error.into_panic()))
+                }
+                None => {
+                    let mut emitter = Emitter::new(sender, sort);
+
+                    emitter.emit(Event::SourceStart(source.id.clone())).await;
+                    let stats = sqlite_check_rows(
+                        &db,
+                        &source.id,
+                        &source.query,
+                        &pattern,
+                        &source_progress,
+                        &limits,
+                        &mut emitter,
+                    )
+                    .await;
+                    emitter
+                        .emit(Event::SourceEnd((source.id.clone(), stats.clone())))
+                        .await;
+                    (stats, emitter.into_events())
+                }
+            };
+
+            progress.finish(&source_progress);
+            (events, stats)
+        }
+    };
+
+    // Tasks are aborted when the search is, so are they when the set is dropped
+    let mut tasks = JoinSet::new();
+    let mut running = HashMap::new();
+    let mut queue = queries.into_iter().enumerate();
+    // Sorted events are sent once source and all sources before it are done
+    let mut done = BTreeMap::new();
+    let mut next = 0;
+    let mut sources = vec![];
+    loop {
+        while tasks.len() < jobs {
+            let Some((idx, source)) = queue.next() else {
+                break;
+            };
+            let id = source.id.clone();
+            let task = tasks.spawn(source_task(source));
+            running.insert(task.id(), (idx, id));
+        }
+
+        let Some(result) = tasks.join_next_with_id().await else {
+            break;
+        };
+        let task_id = match &result {
+            Ok((task_id, _)) => *task_id,
+            Err(error) => error.id(),
+        };
+        let Some((idx, id)) = running.remove(&task_id) else {
+            continue;
+        };
+        let (events, source_stats) = source_result(id, result.map(|(_, result)| result));
+        sources.push((idx, source_stats));
+
+        if !sort {
+            for event in events {
+                send(sender, event).await;
+            }
+            continue;
+        }
+
+        done.insert(idx, events);
+        while let Some(events) = done.remove(&next) {
+            for event in events {
+                send(sender, event).await;
+            }
+            next += 1;
+        }
+    }
+    sources.sort_by_key(|(idx, _)| *idx);
+    let mut sources = sources
+        .into_iter()
+        .map(|(_, source_stats)| source_stats)
+        .collect::<Vec<_>>();
+
+    if options.carve || options.wal_history {
+        let prefix = name
+            .as_ref()
+            .map(|name| format!("{name}::"))
+            .unwrap_or_default();
+        if load.is_some() {
+            log::warn!(
+                "{}: database is loaded into memory",
+                context("Skip reading file of")
+            );
+        } else if encrypted {
+            log::warn!("{}: database is encrypted", context("Skip reading file of"));
+        } else {
+            let trees = Arc::new(sqlite_schema_trees(&db, &state.tables).await?);
+            for (enabled, scan) in [
+                (options.carve, FileScan::Carve),
+                (options.wal_history, FileScan::WalHistory),
+            ] {
+                if enabled {
+                    let (path, trees) = (path.clone(), trees.clone());
+                    let source = file_sources(state, scan, path, immutable, trees, &prefix);
+                    sources.extend(source.await?);
+                }
+            }
+        }
+    }
+
+    db.close().await;
+
+    Ok(sources)
+}
+
+/// Events and statistics of a finished source, panicked task is reported as failed source
+fn source_result(
+    id: String,
+    result: Result<(Vec<Event>, SourceStats), tokio::task::JoinError>,
+) -> (Vec<Event>, (String, SourceStats)) {
+    match result {
+        Ok((events, stats)) => (events, (id, stats)),
+        Err(error) => {
+            let error = SQLError::Io((format!("scan {id}"), std::io::Error::other(error)));
+            let failed = SourceStats {
+                failed: true,
+                ..SourceStats::default()
+            };
+            (vec![Event::Error(error)], (id, failed))
+        }
+    }
+}
+
+/// Events of a source, sent right away or collected to send them once the source is done when sorted.
+///
+/// Sending waits while the receiver is full.
+pub(crate) struct Emitter {
+    sender: Sender<Event>,
+    sorted: Option<Vec<Event>>,
+}
+
+impl Emitter {
+    fn new(sender: Sender<Event>, sort: bool) -> Self {
+        Self {
+            sender,
+            sorted: sort.then(Vec::new),
+        }
+    }
+
+    pub async fn emit(&mut self, event: Event) {
+        match &mut self.sorted {
+            Some(events) => events.push(event),
+            // Receiver is closed when nobody is interested in events anymore
+            None => {
+                let _ = self.sender.send(event).await;
+            }
+        }
+    }
+
+    /// The same for sources scanned in a blocking thread
+    pub fn emit_blocking(&mut self, event: Event) {
+        match &mut self.sorted {
+            Some(events) => events.push(event),
+            None => {
+                let _ = futures::executor::block_on(self.sender.send(event));
+            }
+        }
+    }
+
+    /// Nobody is interested in events anymore
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+
+    fn into_events(self) -> Vec<Event> {
+        self.sorted.unwrap_or_default()
+    }
+}
+
+/// Send event once the receiver has room for it
+async fn send(sender: &Sender<Event>, event: Event) {
+    // Receiver is closed when nobody is interested in events anymore
+    let _ = sender.clone().send(event).await;
+}
+
+/// Ways to read records from the database file directly
+#[derive(Clone, Copy, Debug)]
+enum FileScan {
+    Carve,
+    WalHistory,
+}
+
+/// Tables and indexes stored in the database file, all of them unless tables are selected
+async fn sqlite_schema_trees(
+    db: &Pool<Sqlite>,
+    tables: &[String],
+) -> Result<Vec<SchemaTree>, SQLError> {
+    let schema_error = |error| SQLError::SqlX(("fetch schema".into(), error));
+
+    // Dropped tables are only left in the schema table
+    let mut trees = vec![];
+    if tables.is_empty() {
+        trees.push(SchemaTree {
+            name: "sqlite_schema".into(),
+            root: 1,
+            columns: ["type", "name", "tbl_name", "rootpage", "sql"]
+                .map(String::from)
+                .to_vec(),
+            types: ["text", "text", "text", "int", "text"]
+                .map(String::from)
+                .to_vec(),
+        });
+    }
+
+    let schema_query = "SELECT type, name, rootpage FROM sqlite_schema WHERE rootpage > 0";
+
+    log::debug!("Execute query: {schema_query}");
+
+    for row in db.fetch_all(schema_query).await.map_err(schema_error)? {
+        let kind = row.try_get::<String, usize>(0).map_err(schema_error)?;
+        let name = row.try_get::<String, usize>(1).map_err(schema_error)?;
+        let root = row.try_get::<i64, usize>(2).map_err(schema_error)?;
+
+        if !tables.is_empty() && !tables.contains(&name) {
+            continue;
+        }
+
+        let (columns, types) = match kind.as_str() {
+            "table" => sqlite_stored_columns(db, &name).await?,
+            _ => (vec![], vec![]),
+        };
+
+        trees.push(SchemaTree {
+            name,
+            root: u32::try_from(root).unwrap_or_default(),
+            columns,
+            types,
+        });
+    }
+
+    Ok(trees)
+}
+
+/// Read records from the database file in background
+async fn file_sources(
+    state: &SearchState,
+    scan: FileScan,
+    path: PathBuf,
+    immutable: bool,
+    trees: Arc<Vec<SchemaTree>>,
+    prefix: &str,
+) -> Result<Vec<(String, SourceStats)>, SQLError> {
+    let (pattern, limits) = (state.pattern.clone(), state.limits.clone());
+    let mut sender = state.sender.clone();
+    let task_prefix = prefix.to_owned();
+
+    let result = tokio::task::spawn_blocking(move || {
+        let (pattern, limits, prefix) = (&pattern, &limits, &task_prefix);
+        let mut emit = |event| {
+            let _ = futures::executor::block_on(sender.send(event));
+        };
+        match scan {
+            FileScan::Carve => {
+                carve_database(&path, immutable, &trees, prefix, pattern, limits, &mut emit)
+            }
+            FileScan::WalHistory => {
+                scan_wal_history(&path, &trees, prefix, pattern, limits, &mut emit)
+            }
+        }
+    })
+    .await
+    .map_err(|error| SQLError::Io(("read database file".into(), std::io::Error::other(error))))?;
+
+    // Regular sources are already scanned
+    match result {
+        Ok(sources) => Ok(sources),
+        Err(error) => {
+            send(&state.sender, Event::Error(error)).await;
+            let failed = SourceStats {
+                failed: true,
+                ..SourceStats::default()
+            };
+            let id = match scan {
+                FileScan::Carve => "Carved",
+                FileScan::WalHistory => "WAL history",
+            };
+            Ok(vec![(format!("{prefix}{id}"), failed)])
+        }
+    }
+}
+
+async fn sqlite_select_tables(db: &Pool<Sqlite>) -> Result<Vec<String>, SQLError> {
+    let select_query = "SELECT name FROM sqlite_schema WHERE type = 'table'";
+
+    log::debug!("Execute query: {select_query}");
+
+    let result = db
+        .fetch_all(select_query)
+        .await
+        .map_err(|error| SQLError::SqlX(("fetch tables".into(), error)))?;
+
+    Ok(result
+        .into_iter()
+        .filter_map(|row| match row.try_get::<String, &str>("name") {
+            Ok(value) => Some(value),
+            Err(error) => {
+                SQLError::SqlX(("fetch tables".into(), error)).report(Level::Warn);
+                None
+            }
+        })
+        .collect())
+}
+
+async fn sqlite_select_columns(db: &Pool<Sqlite>, table: &str) -> Result<Vec<String>, SQLError> {
+    // Generated columns are returned by `SELECT *`, hidden columns of virtual tables aren't
+    let select_query = "SELECT name FROM pragma_table_xinfo(?) WHERE hidden != 1";
+
+    log::debug!("Execute query: {select_query} with {table}");
+
+    let result = db
+        .fetch_all(sqlx::query(select_query).bind(table))
+        .await
+        .map_err(|error| SQLError::SqlX((format!("fetch columns of {table}"), error)))?;
+
+    result
+        .into_iter()
+        .map(|row| {
+            row.try_get::<String, &str>("name")
+                .map_err(|error| SQLError::SqlX((format!("fetch columns of {table}"), error)))
+        })
+        .collect()
+}
+
+/// Names and declared types of columns stored in records of the table
+async fn sqlite_stored_columns(
+    db: &Pool<Sqlite>,
+    table: &str,
+) -> Result<(Vec<String>, Vec<String>), SQLError> {
+    // Virtual generated columns aren't stored, stored ones keep their place
+    let columns_query = "SELECT name, type FROM pragma_table_xinfo(?) WHERE hidden IN (0, 3)";
+
+    log::debug!("Execute query: {columns_query} with {table}");
+
+    let columns_error = |error| SQLError::SqlX((format!("fetch columns of {table}"), error));
+    let rows = db
+        .fetch_all(sqlx::query(columns_query).bind(table))
+        .await
+        .map_err(columns_error)?;
+
+    rows.into_iter()
+        .map(|row| {
+            let name = row.try_get::<String, usize>(0).map_err(columns_error)?;
+            let declared = row.try_get::<String, usize>(1).map_err(columns_error)?;
+            Ok((name, declared))
+        })
+        .collect()
+}
+
+/// Sample is taken by rowid, so only ordinary tables with rowid can be sampled
+async fn sqlite_has_rowid(db: &Pool<Sqlite>, table: &str) -> Result<bool, SQLError> {
+    let rowid_query =
+        "SELECT count(*) FROM pragma_table_list WHERE name = ? AND type = 'table' AND wr = 0";
+
+    log::debug!("Execute query: {rowid_query} with {table}");
+
+    let row = db
+        .fetch_one(sqlx::query(rowid_query).bind(table))
+        .await
+        .map_err(|error| SQLError::SqlX((format!("fetch kind of {table}"), error)))?;
+    let count = row
+        .try_get::<i64, usize>(0)
+        .map_err(|error| SQLError::SqlX((format!("fetch kind of {table}"), error)))?;
+
+    Ok(count > 0)
+}
+
+/// Estimated number of rows in the table from `sqlite_stat1` statistics.
+///
+/// Counting rows would read the whole table once more, so the total is unknown
+/// unless `ANALYZE` was run.
+async fn sqlite_count_rows(db: &Pool<Sqlite>, table: &str) -> Option<u64> {
+    let stat_query = "SELECT stat FROM sqlite_stat1 WHERE tbl = ? ORDER BY idx IS NOT NULL LIMIT 1";
+
+    log::debug!("Execute query: {stat_query} with {table}");
+
+    // Table doesn't exist unless `ANALYZE` was run
+    db.fetch_optional(sqlx::query(stat_query).bind(table))
+        .await
+        .ok()
+        .flatten()
+        .and_then(|row| row.try_get::<String, usize>(0).ok())
+        .and_then(|stat| stat.split(' ').next()?.parse::<u64>().ok())
+}
+
+/// Prepare sample and pattern filters for every table if requested
+async fn sqlite_table_filters(
+    db: &Pool<Sqlite>,
+    tables: &[String],
+    pattern: &Pattern,
+    prefilter: bool,
+    sample: Option<f64>,
+    dialect: &impl Dialect,
+) -> Result<HashMap<String, Expr>, SQLError> {
+    let mut filters = HashMap::new();
+
+    if !prefilter && sample.is_none() {
+        return Ok(filters);
+    }
+
+    for table in tables {
+        let mut table_filters = vec![];
+
+        if let Some(percent) = sample {
+            if sqlite_has_rowid(db, table).await? {
+                table_filters.push(generate_sample(percent, dialect)?);
+            } else {
+                log::warn!("Table {table} has no rowid, scan it without sampling");
+            }
+        }
+
+        if prefilter {
+            let columns = sqlite_select_columns(db, table).await?;
+            if let Some(filter) = generate_filter(pattern, &columns, dialect)? {
+                table_filters.push(filter);
+            }
+        }
+
+        if let Some(filter) = combine_filters(table_filters) {
+            filters.insert(table.clone(), filter);
+        }
+    }
+
+    Ok(filters)
+}
@@ -1,6 +1,6 @@
 use sqlparser::ast::helpers::attached_token::AttachedToken;
 use sqlparser::ast::{
-    GroupByExpr, Ident, Select, SelectFlavor, SelectItem, SetExpr, Statement, TableFactor,
+    Expr, GroupByExpr, Ident, Select, SelectFlavor, SelectItem, SetExpr, Statement, TableFactor,
     TableWithJoins, WildcardAdditionalOptions,
 };
 use sqlparser::dialect::Dialect;
@@ -10,17 +10,21 @@ use sqlparser::tokenizer::Span;
 use crate::error::{QueryError, SQLError};
 
 ///
-///  Generates wildcard select for given dialect:
+///  Generates wildcard select for given dialect with optional `WHERE` clause:
 ///
-/// ```rust
+/// ```ignore
 /// // connect to SQLite
 /// use sqlparser::dialect::SQLiteDialect;
 /// let driver = SQLiteDialect{};
-/// let query = generate_select("table", driver);
+/// let query = generate_select("table", None, driver);
 /// assert_eq!("SELECT * FROM `table`", query.as_str());
 /// ```
 ///
-pub(crate) fn generate_select(table_name: &str, dialect: &impl Dialect) -> String {
+pub(crate) fn generate_select(
+    table_name: &str,
+    selection: Option<Expr>,
+    dialect: &impl Dialect,
+) -> String {
     let ast = SetExpr::Select(Box::new(Select {
         flavor: SelectFlavor::Standard,
         distinct: None,
@@ -59,7 +63,7 @@ pub(crate) fn generate_select(table_name: &str, dialect: &impl Dialect) -> Strin
         }]
         .to_vec(),
         lateral_views: vec![],
-        selection: None,
+        selection,
         group_by: GroupByExpr::Expressions(vec![], vec![]),
         cluster_by: vec![],
         distribute_by: vec![],
@@ -0,0 +1,899 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::ops::Range;
+
+/// Database header at the beginning of the first page
+pub(crate) const HEADER_SIZE: usize = 100;
+
+const HEADER_MAGIC: &[u8] = b"SQLite format 3\0";
+
+/// Record header is never that large for real tables
+const MAX_RECORD_HEADER_SIZE: u64 = 4096;
+
+const WAL_HEADER_SIZE: usize = 32;
+
+const WAL_FRAME_HEADER_SIZE: usize = 24;
+
+const JOURNAL_MAGIC: &[u8] = &[0xd9, 0xd5, 0x05, 0xf9, 0x20, 0xa1, 0x63, 0xd7];
+
+/// Table or index stored in a b-tree
+#[derive(Clone, Debug)]
+pub(crate) struct SchemaTree {
+    pub name: String,
+    pub root: u32,
+    /// Column names in order they are stored in records, empty for indexes
+    pub columns: Vec<String>,
+    /// Declared types of the columns, empty when a column has no type
+    pub types: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TextEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+/// Fields of database header needed to read pages
+#[derive(Clone, Debug)]
+pub(crate) struct FileHeader {
+    pub page_size: usize,
+    /// Bytes reserved at the end of every page, e.g. by encryption extensions
+    pub reserved: usize,
+    pub freelist_trunk: u32,
+    pub encoding: TextEncoding,
+}
+
+impl FileHeader {
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        if data.len() < HEADER_SIZE || !data.starts_with(HEADER_MAGIC) {
+            return Err("not a SQLite 3 database or the database is encrypted".into());
+        }
+
+        let page_size = match read_u16(data, 16) {
+            1 => 65536,
+            size => check_page_size(u32::from(size))?,
+        };
+
+        let encoding = match read_u32(data, 56) {
+            // Empty database has no encoding yet
+            0 | 1 => TextEncoding::Utf8,
+            2 => TextEncoding::Utf16Le,
+            3 => TextEncoding::Utf16Be,
+            encoding => return Err(format!("invalid text encoding {encoding}")),
+        };
+
+        Ok(Self {
+            page_size,
+            reserved: usize::from(data[20]),
+            freelist_trunk: read_u32(data, 32),
+            encoding,
+        })
+    }
+
+    /// Page size without reserved bytes
+    pub fn usable_size(&self) -> usize {
+        self.page_size - self.reserved
+    }
+}
+
+/// Database file split into pages
+pub(crate) struct DatabaseFile<'a> {
+    data: &'a [u8],
+    pub header: FileHeader,
+}
+
+impl<'a> DatabaseFile<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, String> {
+        let header = FileHeader::parse(data)?;
+        Ok(Self { data, header })
+    }
+
+    pub fn page_count(&self) -> u32 {
+        u32::try_from(self.data.len() / self.header.page_size).unwrap_or(u32::MAX)
+    }
+
+    /// Usable part of a page, pages are numbered from 1
+    pub fn page(&self, number: u32) -> Option<&'a [u8]> {
+        let start = usize::try_from(number.checked_sub(1)?).ok()? * self.header.page_size;
+        self.data.get(start..start + self.header.usable_size())
+    }
+
+    /// Trunk and leaf pages of the freelist
+    pub fn freelist(&self) -> (Vec<u32>, Vec<u32>) {
+        let (mut trunks, mut leaves) = (vec![], vec![]);
+        let mut visited = HashSet::new();
+        let mut next = self.header.freelist_trunk;
+
+        while next != 0 && visited.insert(next) {
+            let Some(page) = self.page(next) else {
+                break;
+            };
+            trunks.push(next);
+
+            let count = read_u32(page, 4) as usize;
+            leaves.extend(
+                (0..count.min((page.len() - 8) / 4))
+                    .map(|index| read_u32(page, 8 + index * 4))
+                    .filter(|&leaf| leaf != 0 && leaf <= self.page_count()),
+            );
+            next = read_u32(page, 0);
+        }
+
+        (trunks, leaves)
+    }
+
+    /// Pages of a b-tree starting with the root page
+    pub fn btree_pages(&self, root: u32) -> Vec<u32> {
+        let mut pages = vec![];
+        self.walk_btree(root, |number, _| pages.push(number));
+        pages
+    }
+
+    /// Leaf pages of a b-tree in key order
+    pub fn leaf_pages(&self, root: u32) -> Vec<u32> {
+        let mut leaves = vec![];
+        self.walk_btree(root, |number, btree| {
+            if !btree.kind.is_interior() {
+                leaves.push(number);
+            }
+        });
+        leaves
+    }
+
+    /// Visit pages depth-first with children in key order, every page is visited once
+    fn walk_btree(&self, root: u32, mut visit: impl FnMut(u32, &BtreePage)) {
+        let mut visited = HashSet::new();
+        let mut stack = vec![root];
+
+        while let Some(number) = stack.pop() {
+            if !visited.insert(number) {
+                continue;
+            }
+            let Some(page) = self.page(number) else {
+                continue;
+            };
+            let Some(btree) = BtreePage::parse(page, header_offset(number)) else {
+                continue;
+            };
+            visit(number, &btree);
+
+            if btree.kind.is_interior() {
+                // Stack is popped from the end, so the right-most child goes first
+                stack.extend(btree.right_most);
+                let children = btree
+                    .cell_offsets(page)
+                    .filter_map(|offset| page.get(offset..offset + 4))
+                    .map(|child| read_u32(child, 0))
+                    .collect::<Vec<_>>();
+                stack.extend(children.into_iter().rev());
+            }
+        }
+    }
+
+    /// Payload of a cell of a leaf or index page with overflow pages returned by `overflow_page`
+    pub fn cell<'p, 'o>(
+        &self,
+        page: &'p [u8],
+        kind: PageKind,
+        offset: usize,
+        overflow_page: impl Fn(u32) -> Option<&'o [u8]>,
+    ) -> Option<BtreeCell<'p>> {
+        let data = match kind {
+            PageKind::LeafTable | PageKind::LeafIndex => page.get(offset..)?,
+            // Left child pointer goes first
+            PageKind::InteriorIndex => page.get(offset + 4..)?,
+            PageKind::InteriorTable => return None,
+        };
+
+        let (size, mut position) = read_varint(data)?;
+        let rowid = if kind == PageKind::LeafTable {
+            let (rowid, length) = read_varint(&data[position..])?;
+            position += length;
+            Some(rowid as i64)
+        } else {
+            None
+        };
+
+        let size = usize::try_from(size).ok()?;
+        let usable = self.header.usable_size();
+        let max_local = match kind {
+            PageKind::LeafTable => usable - 35,
+            _ => (usable - 12) * 64 / 255 - 23,
+        };
+        let local = if size <= max_local {
+            size
+        } else {
+            let min_local = (usable - 12) * 32 / 255 - 23;
+            let local = min_local + (size - min_local) % (usable - 4);
+            if local <= max_local {
+                local
+            } else {
+                min_local
+            }
+        };
+
+        let local_payload = data.get(position..position + local)?;
+        if local == size {
+            return Some(BtreeCell {
+                rowid,
+                payload: Cow::Borrowed(local_payload),
+            });
+        }
+
+        let mut payload = local_payload.to_vec();
+        let mut next = read_u32(data.get(position + local..position + local + 4)?, 0);
+        let mut visited = HashSet::new();
+        while payload.len() < size && next != 0 && visited.insert(next) {
+            let overflow = overflow_page(next)?;
+            let length = (size - payload.len()).min(overflow.len() - 4);
+            payload.extend_from_slice(&overflow[4..4 + length]);
+            next = read_u32(overflow, 0);
+        }
+
+        (payload.len() == size).then_some(BtreeCell {
+            rowid,
+            payload: Cow::Owned(payload),
+        })
+    }
+}
+
+/// Cell of a b-tree page
+pub(crate) struct BtreeCell<'p> {
+    /// Only cells of table leaf pages have rowid
+    pub rowid: Option<i64>,
+    /// Record with overflow pages joined
+    pub payload: Cow<'p, [u8]>,
+}
+
+/// B-tree header of the first page follows the database header
+pub(crate) fn header_offset(page: u32) -> usize {
+    if page == 1 {
+        HEADER_SIZE
+    } else {
+        0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PageKind {
+    InteriorIndex,
+    InteriorTable,
+    LeafIndex,
+    LeafTable,
+}
+
+impl PageKind {
+    pub fn is_interior(self) -> bool {
+        matches!(self, PageKind::InteriorIndex | PageKind::InteriorTable)
+    }
+}
+
+/// B-tree page header
+#[derive(Clone, Debug)]
+pub(crate) struct BtreePage {
+    pub kind: PageKind,
+    first_freeblock: usize,
+    cell_count: usize,
+    content_start: usize,
+    pub right_most: Option<u32>,
+    /// Offset of the cell pointer array
+    cells_offset: usize,
+}
+
+impl BtreePage {
+    pub fn parse(page: &[u8], header_offset: usize) -> Option<Self> {
+        let header = page.get(header_offset..header_offset + 12)?;
+        let kind = match header[0] {
+            2 => PageKind::InteriorIndex,
+            5 => PageKind::InteriorTable,
+            10 => PageKind::LeafIndex,
+            13 => PageKind::LeafTable,
+            _ => return None,
+        };
+
+        let (header_size, right_most) = if kind.is_interior() {
+            (12, Some(read_u32(header, 8)))
+        } else {
+            (8, None)
+        };
+
+        let content_start = match read_u16(header, 5) {
+            0 => 65536,
+            start => usize::from(start),
+        };
+
+        let btree = Self {
+            kind,
+            first_freeblock: usize::from(read_u16(header, 1)),
+            cell_count: usize::from(read_u16(header, 3)),
+            content_start,
+            right_most,
+            cells_offset: header_offset + header_size,
+        };
+
+        (btree.cells_end() <= btree.content_start.min(page.len())).then_some(btree)
+    }
+
+    fn cells_end(&self) -> usize {
+        self.cells_offset + self.cell_count * 2
+    }
+
+    /// Offsets of cells in the page
+    pub fn cell_offsets<'p>(&self, page: &'p [u8]) -> impl Iterator<Item = usize> + 'p {
+        let offsets = self.cells_offset..self.cells_end();
+        offsets
+            .step_by(2)
+            .map(move |offset| usize::from(read_u16(page, offset)))
+    }
+
+    /// Space between cell pointer array and cell content
+    pub fn unallocated(&self, page: &[u8]) -> Range<usize> {
+        self.cells_end()..self.content_start.min(page.len())
+    }
+
+    /// Freeblocks with their headers, the header overwrites the beginning of a deleted cell
+    pub fn freeblocks(&self, page: &[u8]) -> Vec<Range<usize>> {
+        let mut freeblocks = vec![];
+        let mut visited = HashSet::new();
+        let mut next = self.first_freeblock;
+        while next != 0 && next + 4 <= page.len() && visited.insert(next) {
+            let size = usize::from(read_u16(page, next + 2));
+            if size >= 4 {
+                freeblocks.push(next..(next + size).min(page.len()));
+            }
+            next = usize::from(read_u16(page, next));
+        }
+
+        freeblocks
+    }
+}
+
+/// Decoded value of a record
+#[derive(Clone, Debug)]
+pub(crate) enum RecordValue<'a> {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(&'a [u8]),
+    Blob(&'a [u8]),
+}
+
+/// Record with values in the parsed data
+#[derive(Clone, Debug)]
+pub(crate) struct Record<'a> {
+    pub values: Vec<RecordValue<'a>>,
+    /// Size of the whole record
+    pub size: usize,
+}
+
+/// Parse record at the beginning of data.
+///
+/// Header must be consistent and values must fit into data,
+/// so random bytes are rarely taken for a record.
+pub(crate) fn parse_record(data: &[u8]) -> Option<Record<'_>> {
+    let mut values = RecordValues::new(data)?;
+    let record = Record {
+        values: values.by_ref().collect(),
+        size: values.size(),
+    };
+
+    values.is_complete().then_some(record)
+}
+
+/// Values of a record which header is lost, serial types of all of them are given.
+///
+/// Values must fit into data, size of the record is the size of its values.
+pub(crate) fn parse_record_body<'a>(serial_types: &[u64], data: &'a [u8]) -> Option<Record<'a>> {
+    let mut size = 0;
+    let values = serial_types
+        .iter()
+        .map(|&serial_type| {
+            let (value, length) = read_value(serial_type, data.get(size..)?)?;
+            size += length;
+            Some(value)
+        })
+        .collect::<Option<_>>()?;
+
+    Some(Record { values, size })
+}
+
+/// Values of a record read one by one without allocation
+pub(crate) struct RecordValues<'a> {
+    data: &'a [u8],
+    /// Offset of the next serial type
+    header: usize,
+    header_end: usize,
+    /// Offset of the next value
+    body: usize,
+    invalid: bool,
+}
+
+impl<'a> RecordValues<'a> {
+    /// `None` when the record header doesn't fit into data
+    pub fn new(data: &'a [u8]) -> Option<Self> {
+        let (header_size, header) = read_varint(data)?;
+        if !(2..=MAX_RECORD_HEADER_SIZE).contains(&header_size) {
+            return None;
+        }
+        let header_end = usize::try_from(header_size).ok()?;
+        if header_end > data.len() {
+            return None;
+        }
+
+        Some(Self {
+            data,
+            header,
+            header_end,
+            body: header_end,
+            invalid: false,
+        })
+    }
+
+    /// All values are read and they match the header
+    pub fn is_complete(&self) -> bool {
+        !self.invalid && self.header == self.header_end
+    }
+
+    /// Size of the record read so far
+    pub fn size(&self) -> usize {
+        self.body
+    }
+
+    fn read(&mut self) -> Option<RecordValue<'a>> {
+        let (serial_type, length) = read_varint(&self.data[self.header..self.header_end])?;
+        let (value, size) = read_value(serial_type, &self.data[self.body..])?;
+        self.header += length;
+        self.body += size;
+
+        Some(value)
+    }
+}
+
+impl<'a> Iterator for RecordValues<'a> {
+    type Item = RecordValue<'a>;
+
+    /// Iteration stops early when a value doesn't fit into data
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.invalid || self.header >= self.header_end {
+            return None;
+        }
+
+        let value = self.read();
+        self.invalid = value.is_none();
+        value
+    }
+}
+
+/// Value of the serial type at the beginning of data and its size
+fn read_value(serial_type: u64, data: &[u8]) -> Option<(RecordValue<'_>, usize)> {
+    let size = serial_type_size(serial_type)?;
+    let bytes = data.get(..size)?;
+
+    let value = match serial_type {
+        0 => RecordValue::Null,
+        1..=6 => RecordValue::Integer(read_int(bytes)),
+        7 => RecordValue::Real(f64::from_bits(read_int(bytes) as u64)),
+        8 => RecordValue::Integer(0),
+        9 => RecordValue::Integer(1),
+        serial_type if serial_type % 2 == 0 => RecordValue::Blob(bytes),
+        _ => RecordValue::Text(bytes),
+    };
+    Some((value, size))
+}
+
+/// Size of a value of the serial type, `None` for reserved types
+pub(crate) fn serial_type_size(serial_type: u64) -> Option<usize> {
+    match serial_type {
+        0 | 8 | 9 => Some(0),
+        1..=4 => Some(serial_type as usize),
+        5 => Some(6),
+        6 | 7 => Some(8),
+        10 | 11 => None,
+        _ => usize::try_from((serial_type - 12) / 2).ok(),
+    }
+}
+
+/// Decode TEXT value, `None` when it isn't valid in the encoding
+pub(crate) fn decode_text(bytes: &[u8], encoding: TextEncoding) -> Option<Cow<'_, str>> {
+    match encoding {
+        TextEncoding::Utf8 => std::str::from_utf8(bytes).ok().map(Cow::Borrowed),
+        TextEncoding::Utf16Le | TextEncoding::Utf16Be => {
+            if !bytes.len().is_multiple_of(2) {
+                return None;
+            }
+            let units = bytes.chunks_exact(2).map(|unit| match encoding {
+                TextEncoding::Utf16Le => u16::from_le_bytes([unit[0], unit[1]]),
+                _ => u16::from_be_bytes([unit[0], unit[1]]),
+            });
+            char::decode_utf16(units)
+                .collect::<Result<String, _>>()
+                .ok()
+                .map(Cow::Owned)
+        }
+    }
+}
+
+/// Read SQLite variable-length integer, returns value and its size
+pub(crate) fn read_varint(data: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (index, &byte) in data.iter().take(9).enumerate() {
+        if index == 8 {
+            return Some(((value << 8) | u64::from(byte), 9));
+        }
+        value = (value << 7) | u64::from(byte & 0x7f);
+        if byte & 0x80 == 0 {
+            return Some((value, index + 1));
+        }
+    }
+    None
+}
+
+/// Version of a page stored in a write-ahead log or a rollback journal
+#[derive(Clone, Debug)]
+pub(crate) struct Frame<'a> {
+    /// Frame or journal record number starting with `1`
+    pub number: u32,
+    pub page: u32,
+    /// Transaction of the frame starting with `1`, `None` when it isn't committed
+    pub commit: Option<u32>,
+    pub data: &'a [u8],
+}
+
+/// Frames of a write-ahead log with page size.
+///
+/// Reading stops at the first frame with a wrong salt or checksum,
+/// frames after it are left from earlier transactions and SQLite ignores them too.
+pub(crate) fn wal_frames(data: &[u8]) -> Result<(usize, Vec<Frame<'_>>), String> {
+    if data.len() < WAL_HEADER_SIZE {
+        return Err("write-ahead log is too short".into());
+    }
+
+    let big_endian = match read_u32(data, 0) {
+        0x377f0682 => false,
+        0x377f0683 => true,
+        _ => return Err("invalid write-ahead log header".into()),
+    };
+    let page_size = check_page_size(read_u32(data, 8))?;
+
+    let mut checksum = wal_checksum(big_endian, &data[..24], (0, 0));
+    if checksum != (read_u32(data, 24), read_u32(data, 28)) {
+        return Err("invalid write-ahead log header checksum".into());
+    }
+    let salt = &data[16..24];
+
+    let mut frames = vec![];
+    let (mut commits, mut pending) = (0, 0);
+    let frame_size = WAL_FRAME_HEADER_SIZE + page_size;
+    for (index, frame) in data[WAL_HEADER_SIZE..].chunks_exact(frame_size).enumerate() {
+        let (header, page) = frame.split_at(WAL_FRAME_HEADER_SIZE);
+        if &header[8..16] != salt {
+            break;
+        }
+
+        checksum = wal_checksum(big_endian, &header[..8], checksum);
+        checksum = wal_checksum(big_endian, page, checksum);
+        if checksum != (read_u32(header, 16), read_u32(header, 20)) {
+            break;
+        }
+
+        frames.push(Frame {
+            number: u32::try_from(index + 1).unwrap_or(u32::MAX),
+            page: read_u32(header, 0),
+            commit: None,
+            data: page,
+        });
+
+        // Commit frame has database size after the transaction
+        if read_u32(header, 4) != 0 {
+            commits += 1;
+            for frame in &mut frames[pending..] {
+                frame.commit = Some(commits);
+            }
+            pending = frames.len();
+        }
+    }
+
+    Ok((page_size, frames))
+}
+
+fn wal_checksum(big_endian: bool, data: &[u8], (mut s0, mut s1): (u32, u32)) -> (u32, u32) {
+    let word = |bytes: &[u8]| {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    };
+
+    for words in data.chunks_exact(8) {
+        s0 = s0.wrapping_add(word(&words[..4])).wrapping_add(s1);
+        s1 = s1.wrapping_add(word(&words[4..])).wrapping_add(s0);
+    }
+
+    (s0, s1)
+}
+
+/// Original pages saved in a rollback journal with page size.
+///
+/// Magic number is zero until the journal is synced and the whole header is zeroed
+/// after commit in `PERSIST` mode, so page size of the database is used when it's missing.
+/// Every header has the number of records after it and the next header starts
+/// at a sector boundary. Number of records is unknown until the journal is synced,
+/// records are read up to the end of the file or the first empty record then.
+pub(crate) fn journal_frames(
+    data: &[u8],
+    database_page_size: usize,
+) -> Result<Vec<Frame<'_>>, String> {
+    if data.len() < 28 || !(data.starts_with(JOURNAL_MAGIC) || data[..8] == [0; 8]) {
+        return Err("invalid rollback journal header".into());
+    }
+
+    // SQLite takes them from the first header
+    let sector_size = match read_u32(data, 20) {
+        0 => 512,
+        size => usize::try_from(size.max(28)).unwrap_or(usize::MAX),
+    };
+    let page_size = match read_u32(data, 24) {
+        0 => database_page_size,
+        size => check_page_size(size)?,
+    };
+    let record_size = 4 + page_size + 4;
+
+    let mut frames = vec![];
+    let mut start = 0;
+    // Journal of several transactions has a header before every one of them
+    'headers: while let Some(header) = data.get(start..start + 28) {
+        if start > 0 && !header.starts_with(JOURNAL_MAGIC) {
+            break;
+        }
+
+        let mut offset = start.saturating_add(sector_size);
+        let count = match read_u32(header, 8) {
+            0 | u32::MAX => data.len().saturating_sub(offset) / record_size,
+            count => count as usize,
+        };
+        for _ in 0..count {
+            let Some(record) = data.get(offset..offset + record_size) else {
+                break 'headers;
+            };
+            let page = read_u32(record, 0);
+            if page == 0 {
+                break 'headers;
+            }
+
+            frames.push(Frame {
+                number: u32::try_from(frames.len() + 1).unwrap_or(u32::MAX),
+                page,
+                commit: None,
+                data: &record[4..4 + page_size],
+            });
+            offset += record_size;
+        }
+
+        start = offset.div_ceil(sector_size).saturating_mul(sector_size);
+    }
+
+    Ok(frames)
+}
+
+fn check_page_size(size: u32) -> Result<usize, String> {
+    match size {
+        512..=65536 if size.is_power_of_two() => Ok(size as usize),
+        size => Err(format!("invalid page size {size}")),
+    }
+}
+
+/// Big-endian two's complement integer of 1 to 8 bytes
+fn read_int(bytes: &[u8]) -> i64 {
+    let value = bytes
+        .iter()
+        .fold(0u64, |value, &byte| (value << 8) | u64::from(byte));
+    let shift = 64 - bytes.len() * 8;
+    ((value << shift) as i64) >> shift
+}
+
+pub(crate) fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+pub(crate) fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE_SIZE: usize = 512;
+
+    fn database_header() -> Vec<u8> {
+        let mut header = vec![0; PAGE_SIZE];
+        header[..16].copy_from_slice(HEADER_MAGIC);
+        header[16..18].copy_from_slice(&(PAGE_SIZE as u16).to_be_bytes());
+        header[56..60].copy_from_slice(&1u32.to_be_bytes());
+        header
+    }
+
+    #[test]
+    fn varints_are_read() {
+        assert_eq!(read_varint(&[0x7f]), Some((127, 1)));
+        assert_eq!(read_varint(&[0x81, 0x00, 0xff]), Some((128, 2)));
+        assert_eq!(read_varint(&[0xff; 9]), Some((u64::MAX, 9)));
+        assert_eq!(read_varint(&[0x81]), None);
+    }
+
+    #[test]
+    fn records_are_parsed() {
+        let data = [4, 1, 23, 0, 0xd6, b'h', b'e', b'l', b'l', b'o', 0xff];
+        let record = parse_record(&data).unwrap();
+
+        assert_eq!(record.size, 10);
+        assert!(matches!(
+            record.values[..],
+            [
+                RecordValue::Integer(-42),
+                RecordValue::Text(b"hello"),
+                RecordValue::Null
+            ]
+        ));
+        assert!(parse_record(&data[..8]).is_none());
+        // Reserved serial type
+        assert!(parse_record(&[2, 10]).is_none());
+    }
+
+    #[test]
+    fn record_body_is_parsed_with_given_types() {
+        let record = parse_record_body(&[1, 9, 14], &[7, 0xde, 0xad]).unwrap();
+
+        assert_eq!(record.size, 2);
+        assert!(matches!(
+            record.values[..],
+            [
+                RecordValue::Integer(7),
+                RecordValue::Integer(1),
+                RecordValue::Blob([0xde])
+            ]
+        ));
+        assert!(parse_record_body(&[1, 16], &[7, 0xde]).is_none());
+    }
+
+    #[test]
+    fn overflow_pages_are_joined() {
+        let payload = (0..600).map(|index| index as u8).collect::<Vec<_>>();
+        // Local part of the payload is `min_local + (size - min_local) % (usable - 4)`
+        let local = 39 + (600 - 39) % (PAGE_SIZE - 4);
+
+        let mut leaf = vec![0; PAGE_SIZE];
+        leaf[..8].copy_from_slice(&[13, 0, 0, 0, 1, 1, 0, 0]);
+        leaf[8..10].copy_from_slice(&100u16.to_be_bytes());
+        let cell = [&[0x84, 0x58, 7][..], &payload[..local], &3u32.to_be_bytes()].concat();
+        leaf[100..100 + cell.len()].copy_from_slice(&cell);
+
+        let mut overflow = vec![0; PAGE_SIZE];
+        overflow[4..].copy_from_slice(&payload[local..]);
+
+        let data = [database_header(), leaf, overflow].concat();
+        let file = DatabaseFile::new(&data).unwrap();
+        let page = file.page(2).unwrap();
+        let btree = BtreePage::parse(page, header_offset(2)).unwrap();
+        let offset = btree.cell_offsets(page).next().unwrap();
+        let cell = file
+            .cell(page, btree.kind, offset, |number| file.page(number))
+            .unwrap();
+
+        assert_eq!(cell.rowid, Some(7));
+        assert_eq!(cell.payload, payload);
+        assert!(file.cell(page, btree.kind, offset, |_| None).is_none());
+    }
+
+    #[test]
+    fn freeblocks_are_listed_once() {
+        let mut page = vec![0; PAGE_SIZE];
+        page[..8].copy_from_slice(&[13, 0, 100, 0, 0, 0, 100, 0]);
+        // Freeblock smaller than its header, then a loop back to the first one
+        page[100..104].copy_from_slice(&[0, 200, 0, 10]);
+        page[200..204].copy_from_slice(&[1, 44, 0, 2]);
+        page[300..304].copy_from_slice(&[0, 100, 0, 20]);
+
+        let btree = BtreePage::parse(&page, 0).unwrap();
+
+        assert_eq!(btree.unallocated(&page), 8..100);
+        assert_eq!(btree.freeblocks(&page), [100..110, 300..320]);
+    }
+
+    fn journal_header(records: u32) -> Vec<u8> {
+        let mut header = vec![0; PAGE_SIZE];
+        header[..8].copy_from_slice(JOURNAL_MAGIC);
+        header[8..12].copy_from_slice(&records.to_be_bytes());
+        header[20..24].copy_from_slice(&(PAGE_SIZE as u32).to_be_bytes());
+        header[24..28].copy_from_slice(&(PAGE_SIZE as u32).to_be_bytes());
+        header
+    }
+
+    fn journal_record(page: u32, byte: u8) -> Vec<u8> {
+        [&page.to_be_bytes()[..], &[byte; PAGE_SIZE], &[0; 4]].concat()
+    }
+
+    #[test]
+    fn journal_headers_start_at_sector_boundary() {
+        let mut data = [journal_header(1), journal_record(3, 0xaa)].concat();
+        data.resize(data.len().next_multiple_of(PAGE_SIZE), 0);
+        // Number of records is unknown in the last header
+        data.extend([journal_header(0), journal_record(5, 0xbb)].concat());
+
+        let frames = journal_frames(&data, 4096).unwrap();
+        let pages = frames
+            .iter()
+            .map(|frame| (frame.number, frame.page, frame.data[0]))
+            .collect::<Vec<_>>();
+        assert_eq!(pages, [(1, 3, 0xaa), (2, 5, 0xbb)]);
+        assert!(frames.iter().all(|frame| frame.data.len() == PAGE_SIZE));
+
+        // Stale data after the records isn't a header
+        let mut data = [journal_header(1), journal_record(3, 0xaa)].concat();
+        data.resize(data.len().next_multiple_of(PAGE_SIZE), 0xff);
+        data.extend(journal_record(5, 0xbb));
+        assert_eq!(journal_frames(&data, 4096).unwrap().len(), 1);
+    }
+
+    fn wal(frames: &[(u32, u32, u8)]) -> Vec<u8> {
+        let mut data = vec![0; WAL_HEADER_SIZE];
+        data[..4].copy_from_slice(&0x377f0682u32.to_be_bytes());
+        data[8..12].copy_from_slice(&(PAGE_SIZE as u32).to_be_bytes());
+        data[16..24].copy_from_slice(b"saltsalt");
+        let mut checksum = wal_checksum(false, &data[..24], (0, 0));
+        data[24..28].copy_from_slice(&checksum.0.to_be_bytes());
+        data[28..32].copy_from_slice(&checksum.1.to_be_bytes());
+
+        for &(page, size, byte) in frames {
+            let mut header = [&page.to_be_bytes()[..], &size.to_be_bytes(), b"saltsalt"].concat();
+            let data_page = [byte; PAGE_SIZE];
+            checksum = wal_checksum(false, &header[..8], checksum);
+            checksum = wal_checksum(false, &data_page, checksum);
+            header.extend(checksum.0.to_be_bytes());
+            header.extend(checksum.1.to_be_bytes());
+            data.extend(header);
+            data.extend(data_page);
+        }
+        data
+    }
+
+    #[test]
+    fn wal_frames_are_read_up_to_wrong_checksum() {
+        let data = wal(&[(1, 0, 1), (2, 2, 2), (3, 0, 3), (4, 4, 4)]);
+        let (page_size, frames) = wal_frames(&data).unwrap();
+        let commits = frames
+            .iter()
+            .map(|frame| (frame.page, frame.commit))
+            .collect::<Vec<_>>();
+
+        assert_eq!(page_size, PAGE_SIZE);
+        assert_eq!(
+            commits,
+            [(1, Some(1)), (2, Some(1)), (3, Some(2)), (4, Some(2))]
+        );
+
+        // Frames after a corrupt one are ignored, checksums are cumulative
+        let mut corrupt = data.clone();
+        let third = WAL_HEADER_SIZE + 2 * (WAL_FRAME_HEADER_SIZE + PAGE_SIZE);
+        corrupt[third + WAL_FRAME_HEADER_SIZE] ^= 1;
+        let (_, frames) = wal_frames(&corrupt).unwrap();
+        assert_eq!(frames.len(), 2);
+
+        let mut salted = data.clone();
+        salted[third + 8] ^= 1;
+        let (_, frames) = wal_frames(&salted).unwrap();
+        assert_eq!(frames.len(), 2);
+
+        let mut header = data;
+        header[24] ^= 1;
+        assert!(wal_frames(&header).is_err());
+    }
+}
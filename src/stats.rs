@@ -0,0 +1,111 @@
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Counters collected while scanning a single source
+#[derive(Clone, Debug, Default)]
+pub struct SourceStats {
+    pub rows: u64,
+    pub cells: u64,
+    pub matches: u64,
+    pub skipped_null: u64,
+    pub skipped_blob: u64,
+    pub skipped_unknown: u64,
+    pub conversion_errors: u64,
+    /// Errors reading rows and cells
+    pub read_errors: u64,
+    pub timed_out: bool,
+    /// Source was aborted due to errors
+    pub failed: bool,
+    pub elapsed: Duration,
+}
+
+impl SourceStats {
+    pub fn skipped(&self) -> u64 {
+        self.skipped_null + self.skipped_blob + self.skipped_unknown
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.read_errors + self.conversion_errors
+    }
+}
+
+/// Summary of the whole run
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    pub sources: Vec<(String, SourceStats)>,
+    pub elapsed: Duration,
+}
+
+impl Stats {
+    /// Number of sources which weren't scanned completely due to errors or timeouts
+    pub fn failed(&self) -> usize {
+        self.sources
+            .iter()
+            .filter(|(_, source)| source.failed || source.timed_out)
+            .count()
+    }
+
+    /// Write human readable summary, sinks write it to stderr so it's never mixed with matches
+    pub fn report(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut total = SourceStats::default();
+        for (_, source) in &self.sources {
+            total.rows += source.rows;
+            total.cells += source.cells;
+            total.matches += source.matches;
+            total.skipped_null += source.skipped_null;
+            total.skipped_blob += source.skipped_blob;
+            total.skipped_unknown += source.skipped_unknown;
+            total.conversion_errors += source.conversion_errors;
+            total.read_errors += source.read_errors;
+        }
+
+        writeln!(writer, "Sources scanned: {}", self.sources.len())?;
+        writeln!(writer, "Rows: {}, cells: {}", total.rows, total.cells)?;
+        writeln!(
+            writer,
+            "Skipped cells: {} NULL, {} BLOB, {} unknown type",
+            total.skipped_null, total.skipped_blob, total.skipped_unknown
+        )?;
+        writeln!(writer, "Conversion errors: {}", total.conversion_errors)?;
+        writeln!(writer, "Read errors: {}", total.read_errors)?;
+        writeln!(
+            writer,
+            "Timed out: {}",
+            self.sources
+                .iter()
+                .filter(|(_, source)| source.timed_out)
+                .count()
+        )?;
+        writeln!(
+            writer,
+            "Failed: {}",
+            self.sources
+                .iter()
+                .filter(|(_, source)| source.failed)
+                .count()
+        )?;
+        writeln!(writer, "Matches: {}", total.matches)?;
+        writeln!(writer, "Elapsed: {:.3}s", self.elapsed.as_secs_f64())?;
+
+        for (name, source) in &self.sources {
+            writeln!(
+                writer,
+                "{name}: {} rows, {} cells, {} skipped, {} errors, {} matches, {:.3}s",
+                source.rows,
+                source.cells,
+                source.skipped(),
+                source.errors(),
+                source.matches,
+                source.elapsed.as_secs_f64()
+            )?;
+            if source.timed_out {
+                writeln!(writer, "{name}: timed out")?;
+            }
+            if source.failed {
+                writeln!(writer, "{name}: failed")?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use sqlparser::dialect::SQLiteDialect;
+
+use crate::carve::{record_cells, FileSource};
+use crate::error::SQLError;
+use crate::limits::Limits;
+use crate::pattern::Pattern;
+use crate::search::{Event, Location};
+use crate::select::escape_table_name;
+use crate::sqlite_file::{
+    header_offset, journal_frames, parse_record, wal_frames, BtreePage, DatabaseFile, Frame,
+    PageKind, SchemaTree,
+};
+use crate::stats::SourceStats;
+
+/// Search every version of pages saved in `-wal` and `-journal` files of a database.
+///
+/// Every distinct value of a row is reported once, with the first frame it's found in.
+/// Source names start with `prefix`.
+pub(crate) fn scan_wal_history(
+    path: &Path,
+    trees: &[SchemaTree],
+    prefix: &str,
+    pattern: &Pattern,
+    limits: &Limits,
+    emit: &mut impl FnMut(Event),
+) -> Result<Vec<(String, SourceStats)>, SQLError> {
+    let database = read_file(path)?.unwrap_or_default();
+
+    let mut sources = vec![];
+    let mut found = false;
+
+    if let Some(wal) = read_file(&sibling(path, "-wal"))? {
+        found = true;
+        let (page_size, frames) = wal_frames(&wal).map_err(|error| invalid(path, "-wal", error))?;
+        log::debug!("{}-wal: {} valid frames", path.display(), frames.len());
+
+        // Database grows by pages written to the log, larger page numbers are corrupt
+        let max_page = database.len() / page_size + frames.len();
+        let (frames, corrupt): (Vec<_>, Vec<_>) = frames
+            .into_iter()
+            .partition(|frame| (1..=max_page).contains(&(frame.page as usize)));
+        if !corrupt.is_empty() {
+            log::warn!(
+                "{}-wal: skip {} frames with page number out of the database",
+                path.display(),
+                corrupt.len()
+            );
+        }
+
+        // Pages are owned by tables as of the last commit
+        let mut pages = database.clone();
+        for frame in frames.iter().filter(|frame| frame.commit.is_some()) {
+            let start = (frame.page as usize - 1) * page_size;
+            if pages.len() < start + page_size {
+                pages.resize(start + page_size, 0);
+            }
+            pages[start..start + page_size].copy_from_slice(frame.data);
+        }
+
+        let file = DatabaseFile::new(&pages).map_err(|error| invalid(path, "", error))?;
+        let id = format!("{prefix}WAL");
+        sources.extend(scan_frames(
+            &file, &frames, trees, &id, pattern, limits, emit,
+        ));
+    }
+
+    if let Some(journal) = read_file(&sibling(path, "-journal"))? {
+        found = true;
+        let file = DatabaseFile::new(&database).map_err(|error| invalid(path, "", error))?;
+        let frames = journal_frames(&journal, file.header.page_size)
+            .map_err(|error| invalid(path, "-journal", error))?;
+        log::debug!("{}-journal: {} pages", path.display(), frames.len());
+
+        let id = format!("{prefix}Journal");
+        sources.extend(scan_frames(
+            &file, &frames, trees, &id, pattern, limits, emit,
+        ));
+    }
+
+    if !found {
+        log::warn!(
+            "{}: no write-ahead log or rollback journal found",
+            path.display()
+        );
+    }
+
+    Ok(sources)
+}
+
+/// Match records of leaf pages in frames, sources are tables or indexes owning the pages
+fn scan_frames(
+    file: &DatabaseFile<'_>,
+    frames: &[Frame<'_>],
+    trees: &[SchemaTree],
+    kind: &str,
+    pattern: &Pattern,
+    limits: &Limits,
+    emit: &mut impl FnMut(Event),
+) -> Vec<(String, SourceStats)> {
+    let owners = trees
+        .iter()
+        .enumerate()
+        .flat_map(|(index, tree)| {
+            file.btree_pages(tree.root)
+                .into_iter()
+                .map(move |page| (page, index))
+        })
+        .collect::<HashMap<_, _>>();
+
+    // Overflow pages are read as they were when the transaction of the frame was committed
+    let mut versions: HashMap<u32, Vec<&Frame<'_>>> = HashMap::new();
+    let mut commit_ends = HashMap::new();
+    for frame in frames {
+        versions.entry(frame.page).or_default().push(frame);
+        commit_ends.insert(frame.commit, frame.number);
+    }
+    let usable_size = file.header.usable_size();
+    let overflow_page = |number, frame: &Frame<'_>| {
+        let end = match frame.commit {
+            Some(_) => commit_ends
+                .get(&frame.commit)
+                .copied()
+                .unwrap_or(frame.number),
+            // Uncommitted frames are the last ones, journal has a single version of a page
+            None => u32::MAX,
+        };
+        let versions = versions.get(&number).map(Vec::as_slice).unwrap_or_default();
+        match versions.iter().rev().find(|version| version.number <= end) {
+            Some(version) => version.data.get(..usable_size),
+            None => file.page(number),
+        }
+    };
+
+    // Pages which don't belong to any table now are reported together
+    let mut grouped = vec![vec![]; trees.len() + 1];
+    for frame in frames {
+        let owner = owners.get(&frame.page).copied().unwrap_or(trees.len());
+        grouped[owner].push(frame);
+    }
+
+    let dialect = SQLiteDialect {};
+    let mut sources = vec![];
+
+    for (index, frames) in grouped.into_iter().enumerate() {
+        if frames.is_empty() {
+            continue;
+        }
+
+        let (id, columns) = match trees.get(index) {
+            Some(tree) => (
+                format!("{kind} {}", escape_table_name(&tree.name, &dialect)),
+                tree.columns.as_slice(),
+            ),
+            None => (format!("{kind} other pages"), [].as_slice()),
+        };
+
+        let mut source = FileSource::start(id, columns, pattern, limits, emit);
+        let mut seen = HashSet::new();
+
+        'frames: for frame in frames {
+            let page = &frame.data[..usable_size.min(frame.data.len())];
+            let Some(btree) = BtreePage::parse(page, header_offset(frame.page)) else {
+                continue;
+            };
+            if !matches!(btree.kind, PageKind::LeafTable | PageKind::LeafIndex) {
+                continue;
+            }
+
+            for offset in btree.cell_offsets(page) {
+                if !source.is_running(emit) {
+                    break 'frames;
+                }
+
+                let overflow_page = |number| overflow_page(number, frame);
+                let Some(cell) = file.cell(page, btree.kind, offset, overflow_page) else {
+                    continue;
+                };
+                let Some(record) = parse_record(&cell.payload) else {
+                    continue;
+                };
+
+                let values = record_cells(&record, file.header.encoding);
+                let location =
+                    Location::new(frame.page, offset).in_frame(frame.number, frame.commit);
+                let is_new =
+                    |column, value: &str| seen.insert((cell.rowid, column, value.to_owned()));
+                if !source.match_record(values, location, cell.rowid, emit, is_new) {
+                    break 'frames;
+                }
+            }
+        }
+
+        sources.push(source.finish(emit));
+    }
+
+    sources
+}
+
+/// File next to the database, e.g. `app.db-wal`
+pub(crate) fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn read_file(path: &Path) -> Result<Option<Vec<u8>>, SQLError> {
+    match std::fs::read(path) {
+        Ok(data) => Ok(Some(data)),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(SQLError::Io((
+            format!("read \"{}\"", path.display()),
+            error,
+        ))),
+    }
+}
+
+#[inline]
+fn invalid(path: &Path, suffix: &str, error: String) -> SQLError {
+    SQLError::Configuration(format!(
+        "can't read \"{}{suffix}\": {error}",
+        path.display()
+    ))
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use sqlgrep::SearchOptions;
+
+use common::{create_database, search};
+
+#[tokio::test]
+async fn carves_rows_deleted_from_live_table() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("carve.db");
+
+    let inserts = (0..40)
+        .map(|index| {
+            let note = "note".repeat(index);
+            format!("INSERT INTO t (name, note) VALUES ('name{index:03}', '{note}')")
+        })
+        .collect::<Vec<_>>();
+    let mut statements = vec!["CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT, note TEXT)"];
+    statements.extend(inserts.iter().map(String::as_str));
+    statements.push("DELETE FROM t WHERE name IN ('name005', 'name017', 'name030')");
+    create_database(&path, &statements).await;
+
+    let options = SearchOptions {
+        carve: true,
+        ..SearchOptions::default()
+    };
+    let mut carved = search(&path, "name0", options)
+        .await
+        .into_iter()
+        .filter(|found| found.source == "Carved `t`")
+        .map(|found| (found.column, found.value))
+        .collect::<Vec<_>>();
+    carved.sort();
+
+    let expected = ["name005", "name017", "name030"]
+        .map(|name| ("name".to_owned(), name.to_owned()))
+        .to_vec();
+    assert_eq!(carved, expected);
+}
@@ -0,0 +1,42 @@
+// Every test crate includes the module but uses only some of the helpers
+#![allow(dead_code)]
+
+use std::path::Path;
+
+use sqlgrep::{CollectSink, Match, Pattern, PatternKind, PatternOptions, Search, SearchOptions};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode};
+use sqlx::{ConnectOptions as _, Connection as _, Executor as _};
+
+/// Create a database with rollback journal and run statements on it
+pub async fn create_database(path: &Path, statements: &[&str]) {
+    let mut connection = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Delete)
+        .pragma("secure_delete", "off")
+        .connect()
+        .await
+        .unwrap();
+    for statement in statements {
+        connection.execute(*statement).await.unwrap();
+    }
+    connection.close().await.unwrap();
+}
+
+/// Search a fixed string and collect matches, the search must not fail
+pub async fn search(path: &Path, pattern: &str, options: SearchOptions) -> Vec<Match> {
+    let pattern = Pattern::new(pattern, &PatternKind::Fixed, PatternOptions::default()).unwrap();
+    let mut sink = CollectSink::default();
+    let stats = Search::builder()
+        .database(format!("sqlite://{}", path.display()))
+        .pattern(pattern)
+        .options(options)
+        .build()
+        .unwrap()
+        .run_with_sink(&mut sink)
+        .await
+        .unwrap();
+    assert_eq!(stats.failed(), 0);
+
+    sink.matches
+}
@@ -0,0 +1,20 @@
+mod common;
+
+use sqlgrep::SearchOptions;
+
+use common::search;
+
+#[tokio::test]
+async fn csv_rows_with_invalid_utf8_are_loaded() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.csv");
+    std::fs::write(&path, b"a,b\nneedle1,x\n\xff\xfeneedle2,3\nneedle3,\n").unwrap();
+
+    let found = search(&path, "needle", SearchOptions::default())
+        .await
+        .into_iter()
+        .map(|found| found.value)
+        .collect::<Vec<_>>();
+
+    assert_eq!(found, ["needle1", "\\xFF\\xFEneedle2", "needle3"]);
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use sqlgrep::{Engine, SearchOptions};
+
+use common::{create_database, search};
+
+#[tokio::test]
+async fn raw_engine_matches_sql_engine() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("raw.db");
+
+    let mut statements = vec![
+        "CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT, score REAL, data BLOB, note)",
+        "CREATE TABLE keyed (key TEXT PRIMARY KEY, value TEXT) WITHOUT ROWID",
+        "INSERT INTO keyed VALUES ('k1', 'keyed value')",
+        "CREATE TABLE empty (a)",
+    ];
+    let inserts = (0..300)
+        .map(|index| {
+            let note = match index % 4 {
+                0 => "NULL".to_owned(),
+                1 => index.to_string(),
+                2 => format!("'{}'", "long ünïcode text ".repeat(index)),
+                _ => "x'00ff'".to_owned(),
+            };
+            format!(
+                "INSERT INTO t (name, score, data, note) \
+                 VALUES ('name {index}', {index}.5, x'{index:04x}', {note})"
+            )
+        })
+        .collect::<Vec<_>>();
+    statements.extend(inserts.iter().map(String::as_str));
+    statements.extend([
+        "DELETE FROM t WHERE id % 7 = 0",
+        // Rows written before the column was added have its default value
+        "ALTER TABLE t ADD COLUMN added TEXT DEFAULT 'added default'",
+        "UPDATE t SET added = 'set later' WHERE id % 5 = 0",
+    ]);
+    create_database(&path, &statements).await;
+
+    let mut results = vec![];
+    for engine in [Engine::Sqlx, Engine::Raw] {
+        let options = SearchOptions {
+            engine,
+            ..SearchOptions::default()
+        };
+        let found = search(&path, "", options).await;
+        // Only rows read from the file have rowid
+        let read_raw = found.iter().any(|found| found.rowid.is_some());
+        assert_eq!(read_raw, engine == Engine::Raw);
+
+        let mut matches = found
+            .into_iter()
+            .map(|found| (found.source, found.row, found.column, found.value))
+            .collect::<Vec<_>>();
+        matches.sort();
+        results.push(matches);
+    }
+
+    assert!(results[0].len() > 1000);
+    assert_eq!(results[0], results[1]);
+}
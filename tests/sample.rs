@@ -0,0 +1,43 @@
+mod common;
+
+use sqlgrep::SearchOptions;
+
+use common::{create_database, search};
+
+#[tokio::test]
+async fn tables_without_rowid_are_scanned_whole() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("sample.db");
+
+    let inserts = (0..100)
+        .map(|index| {
+            // Rows are sampled by rowid modulo 10000
+            format!(
+                "INSERT INTO keyed VALUES ('key{index:03}', 'needle'); \
+                 INSERT INTO rows VALUES ({}, 'needle')",
+                index * 100
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut statements = vec![
+        "CREATE TABLE keyed (key TEXT PRIMARY KEY, value TEXT) WITHOUT ROWID",
+        "CREATE TABLE rows (id INTEGER PRIMARY KEY, value TEXT)",
+    ];
+    statements.extend(inserts.iter().map(String::as_str));
+    create_database(&path, &statements).await;
+
+    let options = SearchOptions {
+        sample: Some(50.0),
+        ..SearchOptions::default()
+    };
+    let matches = search(&path, "needle", options).await;
+    let count = |source: &str| {
+        matches
+            .iter()
+            .filter(|found| found.source == source)
+            .count()
+    };
+
+    assert_eq!(count("Table `keyed`"), 100);
+    assert_eq!(count("Table `rows`"), 50);
+}